@@ -0,0 +1,169 @@
+//! Protocol for talking to `tokens agent` over its Unix socket.
+//!
+//! Each connection carries one request and one response, both a single line of JSON.
+
+use crate::config::create_private_dir;
+use crate::error::TokensError;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Path of the agent's socket. When set, the CLI fetches tokens from the agent.
+pub const AGENT_SOCK_ENV: &str = "TOKENS_AGENT_SOCK";
+
+/// Longest a peer may take to send its request or read its response.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AgentRequest {
+    pub nickname: String,
+    #[serde(default)]
+    pub refresh_token: bool,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentResponse {
+    Token(String),
+    Error { message: String, exit_code: i32 },
+}
+
+impl From<Result<String, TokensError>> for AgentResponse {
+    fn from(result: Result<String, TokensError>) -> Self {
+        match result {
+            Ok(token) => Self::Token(token),
+            Err(err) => Self::Error {
+                message: err.to_string(),
+                exit_code: err.exit_code(),
+            },
+        }
+    }
+}
+
+/// Socket used when `tokens agent` is started without `--socket`: in the user's
+/// runtime directory if there is one, otherwise next to the config file.
+pub fn default_socket_path(config_path: &Path) -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("tokens").join("agent.sock"),
+        None => config_path.with_file_name("agent.sock"),
+    }
+}
+
+impl AgentResponse {
+    /// Turn the response back into the error the agent hit, so the CLI exits with the same code.
+    pub fn into_result(self, nickname: &str) -> Result<String, TokensError> {
+        match self {
+            Self::Token(token) => Ok(token),
+            Self::Error { exit_code: 3, .. } => {
+                Err(TokensError::ClientNotFound(nickname.to_string()))
+            }
+            Self::Error { exit_code: 9, .. } => Err(TokensError::LoginRequired),
+            Self::Error { message, exit_code } => Err(TokensError::Agent { message, exit_code }),
+        }
+    }
+}
+
+/// Send one request to the agent at `socket`. Fails if the agent can't be reached
+/// or doesn't answer in time.
+pub async fn request(
+    socket: &Path,
+    request: &AgentRequest,
+) -> Result<AgentResponse, Box<dyn Error + Send + Sync>> {
+    let exchange = async {
+        let stream = UnixStream::connect(socket).await?;
+        let (reader, mut writer) = stream.into_split();
+
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        writer.write_all(line.as_bytes()).await?;
+
+        let mut response = String::new();
+        BufReader::new(reader).read_line(&mut response).await?;
+        Ok(serde_json::from_str(&response)?)
+    };
+
+    tokio::time::timeout(IO_TIMEOUT, exchange)
+        .await
+        .map_err(|_| "the agent did not respond")?
+}
+
+/// Listen on `path` with a socket only the current user can connect to, replacing
+/// one left behind by an agent that is no longer running. Anything at `path` that
+/// isn't a socket is left alone.
+pub fn bind(path: &Path) -> Result<UnixListener, TokensError> {
+    if let Some(dir) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        create_private_dir(dir)?;
+    }
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(format!("{} exists and is not a socket.", path.display()).into());
+        }
+        Ok(_) => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(format!("An agent is already listening on {}.", path.display()).into());
+            }
+            fs::remove_file(path)?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let listener = UnixListener::bind(path)
+        .map_err(|e| format!("Failed to listen on {}: {e}", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn response_wire_format() {
+        let token = AgentResponse::from(Ok("abc".to_string()));
+        assert_eq!(serde_json::to_string(&token).unwrap(), r#"{"token":"abc"}"#);
+
+        let error = AgentResponse::from(Err(TokensError::LoginRequired));
+        let json: serde_json::Value = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["error"]["exit_code"], 9);
+
+        let request: AgentRequest = serde_json::from_str(r#"{"nickname": "client"}"#).unwrap();
+        assert!(!request.refresh_token && request.scopes.is_empty());
+    }
+
+    #[test]
+    fn bind_refuses_a_live_socket_and_replaces_a_stale_one() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+
+        let stale = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        drop(stale);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+
+        let live = bind(&socket).unwrap();
+        assert!(bind(&socket).is_err());
+        drop(live);
+    }
+
+    #[test]
+    fn bind_leaves_other_files_alone() {
+        let dir = tempdir().unwrap();
+        let config = dir.path().join("config.json");
+        fs::write(&config, "{}").unwrap();
+
+        let result = bind(&config);
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&config).unwrap(), "{}");
+    }
+}
@@ -1,36 +1,46 @@
 use super::{CommandContext, CommandHandler};
-use crate::types::AuthConfig;
-use std::error::Error;
+use tokens::TokensError;
+use tokens::{AuthConfig, GrantType};
 
 pub struct AddCommand {
     pub nickname: Option<String>,
     pub auth_url: String,
     pub client_id: String,
     pub secret: Option<String>,
+    pub grant_type: GrantType,
 }
 
 impl CommandHandler for AddCommand {
-    async fn execute(&self, context: CommandContext<'_>) -> Result<(), Box<dyn Error>> {
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError> {
         let nickname = match &self.nickname {
             Some(name) => name,
             None => &self.client_id,
         };
 
+        if self.grant_type == GrantType::ClientCredentials && self.secret.is_none() {
+            return Err("The client credentials grant requires --secret.".into());
+        }
+
         let auth_config = AuthConfig {
             auth_url: self.auth_url.clone(),
             client_id: self.client_id.clone(),
-            refresh_token: None,
             secret: self.secret.clone(),
+            grant_type: self.grant_type,
+            ..Default::default()
         };
 
         context
             .config_manager
-            .add_client(context.config, nickname.to_owned(), auth_config);
+            .add_client(context.config, nickname.to_owned(), auth_config.clone());
 
         let config_path = context.config_manager.get_config_path();
         context
             .config_manager
-            .save_config(&config_path, context.config)?;
+            .update_config(&config_path, |config| {
+                context
+                    .config_manager
+                    .add_client(config, nickname.to_owned(), auth_config)
+            })?;
 
         println!("Client '{nickname}' added.");
         Ok(())
@@ -39,12 +49,8 @@ impl CommandHandler for AddCommand {
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        commands::{CommandContext, CommandHandler, add::AddCommand},
-        config::ConfigManager,
-        oauth::TokenManager,
-        types::{ConfigFile, CredentialsProvider},
-    };
+    use crate::commands::{CommandContext, CommandHandler, add::AddCommand};
+    use tokens::{ConfigFile, ConfigManager, CredentialsProvider, GrantType, TokenManager};
 
     use tempfile::tempdir;
 
@@ -58,9 +64,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_add_command() {
-        let _dir = tempdir().unwrap();
-
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let mut config = ConfigFile::default();
 
@@ -69,6 +74,7 @@ mod tests {
             auth_url: "https://example.com".to_string(),
             client_id: "client123".to_string(),
             secret: None,
+            grant_type: GrantType::Password,
         };
 
         let mock_credentials_provider = MockCredentialsProvider;
@@ -88,9 +94,38 @@ mod tests {
         assert_eq!(added_client.client_id, "client123");
     }
 
+    #[tokio::test]
+    async fn test_add_command_client_credentials_without_secret() {
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let mut config = ConfigFile::default();
+
+        let add_command = AddCommand {
+            nickname: Some("service".to_string()),
+            auth_url: "https://example.com".to_string(),
+            client_id: "client123".to_string(),
+            secret: None,
+            grant_type: GrantType::ClientCredentials,
+        };
+
+        let mock_credentials_provider = MockCredentialsProvider;
+        let context = CommandContext {
+            config: &mut config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &mock_credentials_provider,
+        };
+
+        let result = add_command.execute(context).await;
+        assert!(result.is_err());
+        assert!(config.clients.is_empty());
+    }
+
     #[tokio::test]
     async fn test_add_command_without_nickname() {
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let mut config = ConfigFile::default();
 
@@ -99,6 +134,7 @@ mod tests {
             auth_url: "https://example.com".to_string(),
             client_id: "client123".to_string(),
             secret: Some("secret".to_string()),
+            grant_type: GrantType::Password,
         };
 
         let mock_credentials_provider = MockCredentialsProvider;
@@ -0,0 +1,427 @@
+use super::{CommandContext, CommandHandler, acquire_token, refresh_deadline};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokens::ConfigManager;
+use tokens::TokenManager;
+use tokens::TokensError;
+use tokens::agent::{
+    AGENT_SOCK_ENV, AgentRequest, AgentResponse, IO_TIMEOUT, bind, default_socket_path,
+};
+use tokens::{ConfigFile, ConsoleCredentialsProvider};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinSet;
+use tokio::time::{Instant, sleep, sleep_until, timeout};
+
+/// How long to wait before retrying a failed background refresh.
+const REFRESH_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// How long to pause after a failed accept, e.g. when out of file descriptors.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+pub struct AgentCommand {
+    /// Where to listen, instead of the default socket path.
+    pub socket: Option<PathBuf>,
+}
+
+impl CommandHandler for AgentCommand {
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError> {
+        let path = match &self.socket {
+            Some(path) => path.clone(),
+            None => default_socket_path(&context.config_manager.get_config_path()),
+        };
+        let listener = bind(&path)?;
+
+        eprintln!("Agent listening on {}", path.display());
+        println!("export {AGENT_SOCK_ENV}={}", path.display());
+
+        // The agent has no terminal to prompt on, and must not ask itself for tokens.
+        let token_manager = TokenManager::new()
+            .with_expiry_skew(context.token_manager.expiry_skew())
+            .with_interactive(false)
+            .with_warning_hook(|warning| eprintln!("Warning: {warning}"));
+
+        let result = serve(
+            context.config.clone(),
+            context.config_manager.clone(),
+            token_manager,
+            listener,
+        )
+        .await;
+        let _ = fs::remove_file(&path);
+        result
+    }
+}
+
+/// State shared by the connection tasks and the refresh task.
+struct Agent {
+    /// The config as last read or refreshed. Cached tokens are served from here.
+    config: Mutex<ConfigFile>,
+    config_manager: ConfigManager,
+    token_manager: TokenManager,
+    /// Clients whose access tokens the refresh task should keep fresh.
+    watch: mpsc::UnboundedSender<(String, Instant)>,
+}
+
+impl Agent {
+    async fn token(&self, request: &AgentRequest) -> Result<String, TokensError> {
+        if !request.refresh_token
+            && request.scopes.is_empty()
+            && let Some(cached) = self
+                .config
+                .lock()
+                .await
+                .clients
+                .get(&request.nickname)
+                .and_then(|client| client.access_token.as_ref())
+                .filter(|cached| cached.is_valid(self.token_manager.expiry_skew()))
+        {
+            return Ok(cached.access_token.clone());
+        }
+
+        let (token, deadline) = self
+            .fetch(&request.nickname, request.refresh_token, &request.scopes)
+            .await?;
+        // Scoped tokens aren't cached, so there is nothing to keep fresh for them.
+        if request.scopes.is_empty() {
+            let _ = self.watch.send((request.nickname.clone(), deadline));
+        }
+        Ok(token)
+    }
+
+    /// Fetch a token through the config file as the CLI does, then keep the client's
+    /// new state in memory. Returns when the token should next be refreshed.
+    ///
+    /// The in-memory config isn't held meanwhile, so a slow refresh doesn't hold up
+    /// requests for other clients; the config file lock orders concurrent fetches.
+    async fn fetch(
+        &self,
+        nickname: &str,
+        fetch_refresh_token: bool,
+        scopes: &[String],
+    ) -> Result<(String, Instant), TokensError> {
+        let mut config = self.config.lock().await.clone();
+        let mut context = CommandContext {
+            config: &mut config,
+            config_manager: &self.config_manager,
+            token_manager: &self.token_manager,
+            // Never asked: the token manager is non-interactive.
+            credentials_provider: &ConsoleCredentialsProvider,
+        };
+
+        let token = acquire_token(&mut context, nickname, fetch_refresh_token, scopes).await?;
+        let deadline = refresh_deadline(&context, nickname, &token);
+
+        if let Some(client) = config.clients.remove(nickname) {
+            self.config
+                .lock()
+                .await
+                .clients
+                .insert(nickname.to_string(), client);
+        }
+        Ok((token, deadline))
+    }
+}
+
+/// Answer requests until interrupted, each on its own task, while a separate task
+/// keeps the tokens that have been asked for fresh.
+async fn serve(
+    config: ConfigFile,
+    config_manager: ConfigManager,
+    token_manager: TokenManager,
+    listener: UnixListener,
+) -> Result<(), TokensError> {
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    let (watch, watched) = mpsc::unbounded_channel();
+    let agent = Arc::new(Agent {
+        config: Mutex::new(config),
+        config_manager,
+        token_manager,
+        watch,
+    });
+
+    // Dropping the set stops the refresh task along with the agent.
+    let mut refreshing = JoinSet::new();
+    refreshing.spawn(keep_fresh(agent.clone(), watched));
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        eprintln!("Failed to accept connection: {e}");
+                        sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+
+                let agent = agent.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(&agent, stream).await {
+                        eprintln!("Failed to answer request: {e}");
+                    }
+                });
+            }
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            _ = terminate.recv() => return Ok(()),
+        }
+    }
+}
+
+/// Refresh the access token of every watched client shortly before it goes stale.
+async fn keep_fresh(agent: Arc<Agent>, mut watched: mpsc::UnboundedReceiver<(String, Instant)>) {
+    let mut refresh_at: HashMap<String, Instant> = HashMap::new();
+
+    loop {
+        let next_refresh = refresh_at
+            .iter()
+            .min_by_key(|(_, deadline)| **deadline)
+            .map(|(nickname, deadline)| (nickname.clone(), *deadline));
+
+        tokio::select! {
+            Some((nickname, deadline)) = watched.recv() => {
+                // The latest fetch's deadline is the one for the token now cached.
+                refresh_at.insert(nickname, deadline);
+            }
+            _ = sleep_until(next_refresh.as_ref().map_or_else(Instant::now, |(_, at)| *at)),
+                if next_refresh.is_some() =>
+            {
+                let (nickname, _) = next_refresh.unwrap();
+                match agent.fetch(&nickname, false, &[]).await {
+                    Ok((_, deadline)) => {
+                        refresh_at.insert(nickname, deadline);
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Failed to refresh token for '{nickname}', retrying in {REFRESH_RETRY_DELAY:?}: {e}"
+                        );
+                        refresh_at.insert(nickname, Instant::now() + REFRESH_RETRY_DELAY);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Read one request from `stream` and write back the token or error.
+async fn respond(agent: &Agent, stream: UnixStream) -> io::Result<()> {
+    // The socket's mode already limits who can connect; check the peer as well in
+    // case the socket was placed in a shared directory.
+    let peer = stream.peer_cred()?;
+    // SAFETY: geteuid has no memory safety requirements.
+    if peer.uid() != unsafe { libc::geteuid() } {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("rejected connection from uid {}", peer.uid()),
+        ));
+    }
+
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    let read = timeout(IO_TIMEOUT, BufReader::new(reader).read_line(&mut line))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no request received"))??;
+    // Another agent checking whether this one is still alive.
+    if read == 0 {
+        return Ok(());
+    }
+
+    let response = match serde_json::from_str::<AgentRequest>(&line) {
+        Ok(request) => AgentResponse::from(agent.token(&request).await),
+        Err(e) => AgentResponse::from(Err(TokensError::Other(format!("Invalid request: {e}")))),
+    };
+
+    let mut line = serde_json::to_string(&response)?;
+    line.push('\n');
+    timeout(IO_TIMEOUT, writer.write_all(line.as_bytes()))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "response not read"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::Method::POST;
+    use httpmock::MockServer;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+    use tokens::ConfigManager;
+    use tokens::agent;
+    use tokens::{AuthConfig, CachedToken, CredentialsProvider, unix_now};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
+    fn request(nickname: &str) -> AgentRequest {
+        AgentRequest {
+            nickname: nickname.to_string(),
+            refresh_token: false,
+            scopes: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn agent_serves_tokens_over_socket() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "client123".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "cached".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        config_manager
+            .save_config(&config_manager.get_config_path(), &config)
+            .unwrap();
+
+        let listener = bind(&socket).unwrap();
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let requests = async {
+            let token = agent::request(&socket, &request("client")).await.unwrap();
+            let missing = agent::request(&socket, &request("missing")).await.unwrap();
+            (token.into_result("client"), missing.into_result("missing"))
+        };
+
+        tokio::select! {
+            _ = serve(config, config_manager, TokenManager::new(), listener) => panic!("agent stopped"),
+            (token, missing) = requests => {
+                assert_eq!(token.unwrap(), "cached");
+                assert!(matches!(missing, Err(TokensError::ClientNotFound(_))));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn agent_serves_from_memory_while_another_client_refreshes() {
+        let server = MockServer::start();
+        let refresh = server.mock(|when, then| {
+            when.method(POST)
+                .path("/protocol/openid-connect/token")
+                .body_contains("grant_type=refresh_token");
+            then.status(200)
+                .delay(Duration::from_millis(500))
+                .header("content-type", "application/json")
+                .body(r#"{"access_token": "fresh", "expires_in": 300}"#);
+        });
+
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let config_path = dir.path().join("config.json");
+        let config_manager = ConfigManager::with_path(config_path.clone());
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "cached".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "client123".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "cached".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        config.clients.insert(
+            "slow".to_string(),
+            AuthConfig {
+                auth_url: server.url(""),
+                client_id: "client123".to_string(),
+                refresh_token: Some("refresh123".to_string()),
+                ..Default::default()
+            },
+        );
+        config_manager.save_config(&config_path, &config).unwrap();
+        let listener = bind(&socket).unwrap();
+
+        let requests = async {
+            let slow_request = request("slow");
+            let slow = agent::request(&socket, &slow_request);
+            let cached = async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let started = Instant::now();
+                let response = agent::request(&socket, &request("cached")).await.unwrap();
+                (response, started.elapsed())
+            };
+            let (slow, (cached, waited)) = tokio::join!(slow, cached);
+            assert_eq!(cached.into_result("cached").unwrap(), "cached");
+            assert!(waited < Duration::from_millis(300), "waited {waited:?}");
+            assert_eq!(slow.unwrap().into_result("slow").unwrap(), "fresh");
+
+            // The refreshed client was saved, and is now served without the file.
+            let saved = config_manager.read_config(&config_path).unwrap().unwrap();
+            assert_eq!(
+                saved.clients["slow"]
+                    .access_token
+                    .as_ref()
+                    .unwrap()
+                    .access_token,
+                "fresh"
+            );
+            fs::remove_file(&config_path).unwrap();
+            let again = agent::request(&socket, &request("slow")).await.unwrap();
+            assert_eq!(again.into_result("slow").unwrap(), "fresh");
+            assert!(!config_path.exists());
+        };
+
+        tokio::select! {
+            _ = serve(config.clone(), config_manager.clone(), TokenManager::new(), listener) => panic!("agent stopped"),
+            () = requests => {}
+        }
+        refresh.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn unreachable_agent_falls_back_to_local_config() {
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "client123".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "local".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let token_manager = TokenManager::new().with_agent_socket(dir.path().join("none.sock"));
+        let mut context = CommandContext {
+            config: &mut config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &MockCredentialsProvider,
+        };
+
+        let token = acquire_token(&mut context, "client", false, &[]).await;
+        assert_eq!(token.unwrap(), "local");
+    }
+}
@@ -1,27 +1,27 @@
 use super::{CommandContext, CommandHandler};
-use std::error::Error;
+use tokens::TokensError;
 
 pub struct DeleteCommand {
     pub nickname: String,
 }
 
 impl CommandHandler for DeleteCommand {
-    async fn execute(&self, context: CommandContext<'_>) -> Result<(), Box<dyn Error>> {
-        if context
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError> {
+        if !context
             .config_manager
             .remove_client(context.config, &self.nickname)
         {
-            let config_path = context.config_manager.get_config_path();
-            match context
-                .config_manager
-                .save_config(&config_path, context.config)
-            {
-                Ok(()) => println!("Client '{}' removed", self.nickname),
-                Err(e) => println!("Failed to update config file.\n{e}"),
-            }
-        } else {
-            println!("Client '{}' doesn't exist", self.nickname);
+            return Err(TokensError::ClientNotFound(self.nickname.clone()));
         }
+
+        let config_path = context.config_manager.get_config_path();
+        context
+            .config_manager
+            .update_config(&config_path, |config| {
+                context.config_manager.remove_client(config, &self.nickname);
+            })
+            .map_err(|e| TokensError::from(e).context("Failed to update config file"))?;
+        println!("Client '{}' removed", self.nickname);
         Ok(())
     }
 }
@@ -29,12 +29,11 @@ impl CommandHandler for DeleteCommand {
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use tempfile::tempdir;
 
-    use crate::{
-        commands::{CommandContext, CommandHandler, delete::DeleteCommand},
-        config::ConfigManager,
-        oauth::TokenManager,
-        types::{AuthConfig, ConfigFile, CredentialsProvider},
+    use crate::commands::{CommandContext, CommandHandler, delete::DeleteCommand};
+    use tokens::{
+        AuthConfig, ConfigFile, ConfigManager, CredentialsProvider, TokenManager, TokensError,
     };
 
     struct MockCredentialsProvider;
@@ -56,6 +55,7 @@ mod tests {
                         client_id: "client_id_1".to_string(),
                         refresh_token: Some("token1".to_string()),
                         secret: None,
+                        ..Default::default()
                     },
                 ),
                 (
@@ -65,11 +65,13 @@ mod tests {
                         client_id: "client_id_2".to_string(),
                         refresh_token: None,
                         secret: None,
+                        ..Default::default()
                     },
                 ),
             ]
             .into_iter()
             .collect(),
+            ..Default::default()
         };
 
         let config_manager = ConfigManager::new();
@@ -83,10 +85,12 @@ mod tests {
                     client_id: "client_id_2".to_string(),
                     refresh_token: None,
                     secret: None,
+                    ..Default::default()
                 },
             )]
             .into_iter()
             .collect(),
+            ..Default::default()
         };
 
         assert_eq!(config, target);
@@ -94,7 +98,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_delete_command_existing_client() {
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let mut config = ConfigFile {
             clients: {
@@ -106,10 +111,12 @@ mod tests {
                         client_id: "client123".to_string(),
                         refresh_token: Some("refresh123".to_string()),
                         secret: None,
+                        ..Default::default()
                     },
                 );
                 clients
             },
+            ..Default::default()
         };
 
         let delete_command = DeleteCommand {
@@ -131,7 +138,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_delete_command_nonexistent_client() {
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let mut config = ConfigFile::default();
 
@@ -148,6 +156,6 @@ mod tests {
         };
 
         let result = delete_command.execute(context).await;
-        assert!(result.is_ok()); // Command succeeds but prints message that client doesn't exist
+        assert!(matches!(result, Err(TokensError::ClientNotFound(_))));
     }
 }
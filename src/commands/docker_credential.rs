@@ -0,0 +1,290 @@
+use super::{CommandContext, CommandHandler, HostTable, acquire_token, map_host, unmap_host};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use tokens::TokensError;
+use tokens::{DEFAULT_HOST_USERNAME, HostMapping};
+
+/// File name that makes the binary act as `tokens docker-credential`, so it can be
+/// installed as a docker credential helper by linking it under this name.
+pub const DOCKER_HELPER_NAME: &str = "docker-credential-tokens";
+
+/// Docker recognises this exact message as "no credentials stored for this registry".
+const NOT_FOUND: &str = "credentials not found in native keychain";
+
+const REGISTRIES: HostTable = |config| &mut config.registries;
+
+#[derive(Debug, Subcommand)]
+pub enum DockerCredentialAction {
+    /// Print credentials for the registry read from stdin.
+    Get,
+    /// Accept and discard credentials from `docker login`; registries are set up with `map`.
+    Store,
+    /// Remove the mapping, if any, for the registry read from stdin.
+    Erase,
+    /// Print the mapped registries and their usernames.
+    List,
+    /// Answer for a registry with a stored client.
+    Map {
+        registry: String,
+        nickname: String,
+        /// Username to send with the token.
+        #[arg(short, long)]
+        username: Option<String>,
+    },
+    /// Stop answering for a registry.
+    Unmap { registry: String },
+}
+
+pub struct DockerCredentialCommand {
+    pub action: DockerCredentialAction,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+struct Credentials {
+    #[serde(rename = "ServerURL")]
+    server_url: String,
+    username: String,
+    secret: String,
+}
+
+impl CommandHandler for DockerCredentialCommand {
+    async fn execute(&self, mut context: CommandContext<'_>) -> Result<(), TokensError> {
+        match &self.action {
+            DockerCredentialAction::Map {
+                registry,
+                nickname,
+                username,
+            } => {
+                let mapping = HostMapping {
+                    nickname: nickname.clone(),
+                    username: username.clone(),
+                };
+                map_host(&mut context, REGISTRIES, &registry_host(registry), mapping)?;
+                println!("Credentials for '{registry}' now come from '{nickname}'.");
+            }
+            DockerCredentialAction::Unmap { registry } => {
+                unmap_host(&mut context, REGISTRIES, &registry_host(registry))?;
+                println!("Credentials for '{registry}' are no longer provided.");
+            }
+            action => {
+                let mut input = String::new();
+                io::stdin().read_to_string(&mut input)?;
+                let reply = respond(&mut context, action, input.trim()).await?;
+                if !reply.is_empty() {
+                    println!("{reply}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Handle one request from docker, returning the reply.
+async fn respond(
+    context: &mut CommandContext<'_>,
+    action: &DockerCredentialAction,
+    input: &str,
+) -> Result<String, TokensError> {
+    match action {
+        DockerCredentialAction::Get => {
+            let mapping = context
+                .config
+                .registries
+                .get(&registry_host(input))
+                .cloned()
+                .ok_or(NOT_FOUND)?;
+            let token = acquire_token(context, &mapping.nickname, false, &[]).await?;
+
+            let credentials = Credentials {
+                server_url: input.to_string(),
+                username: mapping
+                    .username
+                    .unwrap_or_else(|| DEFAULT_HOST_USERNAME.to_string()),
+                secret: token,
+            };
+            Ok(serde_json::to_string(&credentials)?)
+        }
+        // Tokens are fetched fresh on every `get`, so there is nothing worth keeping
+        // from `docker login`. Succeeding keeps docker from reporting an error.
+        DockerCredentialAction::Store => Ok(String::new()),
+        DockerCredentialAction::Erase => {
+            let host = registry_host(input);
+            if context.config.registries.contains_key(&host) {
+                unmap_host(context, REGISTRIES, &host)?;
+            }
+            Ok(String::new())
+        }
+        DockerCredentialAction::List => {
+            let registries: HashMap<&String, &str> = context
+                .config
+                .registries
+                .iter()
+                .map(|(registry, mapping)| {
+                    let username = mapping.username.as_deref().unwrap_or(DEFAULT_HOST_USERNAME);
+                    (registry, username)
+                })
+                .collect();
+            Ok(serde_json::to_string(&registries)?)
+        }
+        DockerCredentialAction::Map { .. } | DockerCredentialAction::Unmap { .. } => {
+            unreachable!("handled without reading stdin")
+        }
+    }
+}
+
+/// The hostname docker may refer to a registry by, with or without a scheme and path,
+/// e.g. `https://harbor.internal/v2/` becomes `harbor.internal`.
+fn registry_host(server_url: &str) -> String {
+    let host = server_url
+        .split_once("://")
+        .map_or(server_url, |(_, rest)| rest);
+    host.split('/').next().unwrap_or_default().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use tokens::ConfigManager;
+    use tokens::TokenManager;
+    use tokens::{AuthConfig, CachedToken, ConfigFile, CredentialsProvider};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
+    fn harbor_config() -> ConfigFile {
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "harbor".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "harbor".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "cached".to_string(),
+                    expires_at: 4_000_000_000,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        config
+    }
+
+    async fn run(
+        config: &mut ConfigFile,
+        action: DockerCredentialAction,
+        input: &str,
+    ) -> Result<String, TokensError> {
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let mut context = CommandContext {
+            config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &MockCredentialsProvider,
+        };
+        respond(&mut context, &action, input).await
+    }
+
+    fn map_harbor(config: &mut ConfigFile) {
+        config.registries.insert(
+            "harbor.internal".to_string(),
+            HostMapping {
+                nickname: "harbor".to_string(),
+                username: None,
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn get_returns_the_mapped_clients_token() {
+        let mut config = harbor_config();
+        map_harbor(&mut config);
+
+        let reply = run(&mut config, DockerCredentialAction::Get, "Harbor.internal")
+            .await
+            .unwrap();
+
+        let credentials: Credentials = serde_json::from_str(&reply).unwrap();
+        assert_eq!(
+            credentials,
+            Credentials {
+                server_url: "Harbor.internal".to_string(),
+                username: DEFAULT_HOST_USERNAME.to_string(),
+                secret: "cached".to_string(),
+            }
+        );
+
+        let list = run(&mut config, DockerCredentialAction::List, "")
+            .await
+            .unwrap();
+        assert_eq!(list, r#"{"harbor.internal":"oauth2"}"#);
+    }
+
+    #[tokio::test]
+    async fn get_reports_unmapped_registries_as_not_found() {
+        let mut config = harbor_config();
+
+        let result = run(&mut config, DockerCredentialAction::Get, "other.internal").await;
+
+        assert_eq!(result.unwrap_err().to_string(), NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn store_changes_nothing() {
+        let mut config = harbor_config();
+
+        let reply = run(
+            &mut config,
+            DockerCredentialAction::Store,
+            r#"{"ServerURL": "harbor.internal", "Username": "harbor", "Secret": "x"}"#,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(reply, "");
+        assert!(config.registries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn erase_removes_the_mapping_and_ignores_unmapped_registries() {
+        let mut config = harbor_config();
+        map_harbor(&mut config);
+
+        run(&mut config, DockerCredentialAction::Erase, "other.internal")
+            .await
+            .unwrap();
+        assert_eq!(config.registries.len(), 1);
+
+        run(
+            &mut config,
+            DockerCredentialAction::Erase,
+            "harbor.internal",
+        )
+        .await
+        .unwrap();
+        assert!(config.registries.is_empty());
+    }
+
+    #[test]
+    fn registry_host_strips_scheme_and_path() {
+        assert_eq!(
+            registry_host("https://index.docker.io/v1/"),
+            "index.docker.io"
+        );
+        assert_eq!(
+            registry_host("harbor.internal:5000"),
+            "harbor.internal:5000"
+        );
+    }
+}
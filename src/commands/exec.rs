@@ -0,0 +1,298 @@
+use super::{CommandContext, CommandHandler, acquire_token};
+use std::process::ExitStatus;
+use tokens::TokensError;
+use tokio::process::{Child, Command};
+
+pub struct ExecCommand {
+    pub nickname: String,
+    /// Variable the access token is exported as.
+    pub env_var: String,
+    /// Also export the refresh token under this name.
+    pub refresh_token_var: Option<String>,
+    /// Also export `Bearer <token>` under this name.
+    pub header_var: Option<String>,
+    pub scopes: Vec<String>,
+    /// Program followed by its arguments.
+    pub command: Vec<String>,
+}
+
+impl CommandHandler for ExecCommand {
+    async fn execute(&self, mut context: CommandContext<'_>) -> Result<(), TokensError> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or("No command given to run.")?;
+
+        let token = acquire_token(&mut context, &self.nickname, false, &self.scopes).await?;
+
+        let mut command = Command::new(program);
+        command.args(args).env(&self.env_var, &token);
+
+        if let Some(name) = &self.header_var {
+            command.env(name, format!("Bearer {token}"));
+        }
+
+        if let Some(name) = &self.refresh_token_var {
+            let refresh_token = context
+                .config
+                .clients
+                .get(&self.nickname)
+                .and_then(|client| client.refresh_token.as_ref())
+                .ok_or_else(|| format!("Client '{}' has no refresh token.", self.nickname))?;
+            command.env(name, refresh_token);
+        }
+
+        let child = command
+            .spawn()
+            .map_err(|e| format!("Failed to run '{program}': {e}"))?;
+
+        match exit_code(wait_forwarding_signals(child).await?) {
+            0 => Ok(()),
+            code => Err(TokensError::ChildExited(code)),
+        }
+    }
+}
+
+/// Wait for the child, passing on signals sent to this process so that it can
+/// shut down cleanly instead of being orphaned.
+#[cfg(unix)]
+async fn wait_forwarding_signals(mut child: Child) -> std::io::Result<ExitStatus> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let kinds = [
+        SignalKind::interrupt(),
+        SignalKind::terminate(),
+        SignalKind::hangup(),
+        SignalKind::quit(),
+        SignalKind::user_defined1(),
+        SignalKind::user_defined2(),
+    ];
+    let mut signals = Vec::new();
+    for kind in kinds {
+        signals.push((kind, signal(kind)?));
+    }
+
+    // Signals the terminal sends to its whole foreground process group.
+    let terminal_kinds = [
+        SignalKind::interrupt(),
+        SignalKind::hangup(),
+        SignalKind::quit(),
+    ];
+
+    loop {
+        tokio::select! {
+            status = child.wait() => return status,
+            kind = next_signal(&mut signals) => {
+                // The child inherited our process group, so if that is the terminal's
+                // foreground group it got this signal too; forwarding would repeat it.
+                if terminal_kinds.contains(&kind) && in_foreground_process_group() {
+                    continue;
+                }
+                if let Some(pid) = child.id() {
+                    // SAFETY: kill has no memory safety requirements.
+                    unsafe { libc::kill(pid as libc::pid_t, kind.as_raw_value()) };
+                }
+            }
+        }
+    }
+}
+
+/// Whether this process is in the foreground process group of its controlling terminal.
+#[cfg(unix)]
+fn in_foreground_process_group() -> bool {
+    use std::os::fd::AsRawFd;
+
+    let Ok(tty) = std::fs::File::open("/dev/tty") else {
+        return false;
+    };
+    // SAFETY: tcgetpgrp only reads the terminal's state and getpgrp can't fail.
+    unsafe { libc::tcgetpgrp(tty.as_raw_fd()) == libc::getpgrp() }
+}
+
+/// Wait for whichever of `signals` arrives first.
+#[cfg(unix)]
+async fn next_signal(
+    signals: &mut [(tokio::signal::unix::SignalKind, tokio::signal::unix::Signal)],
+) -> tokio::signal::unix::SignalKind {
+    std::future::poll_fn(|cx| {
+        for (kind, signal) in signals.iter_mut() {
+            if signal.poll_recv(cx).is_ready() {
+                return std::task::Poll::Ready(*kind);
+            }
+        }
+        std::task::Poll::Pending
+    })
+    .await
+}
+
+#[cfg(not(unix))]
+async fn wait_forwarding_signals(mut child: Child) -> std::io::Result<ExitStatus> {
+    child.wait().await
+}
+
+/// The child's exit code, or `128 + signal` if it was killed, as shells report it.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+    use tokens::ConfigManager;
+    use tokens::TokenManager;
+    use tokens::{AuthConfig, CachedToken, ConfigFile, CredentialsProvider, unix_now};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
+    async fn run(script: &str) -> Result<(), TokensError> {
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "client123".to_string(),
+                refresh_token: Some("refresh123".to_string()),
+                access_token: Some(CachedToken {
+                    access_token: "token123".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let context = CommandContext {
+            config: &mut config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &MockCredentialsProvider,
+        };
+
+        ExecCommand {
+            nickname: "client".to_string(),
+            env_var: "TOKEN".to_string(),
+            refresh_token_var: Some("REFRESH".to_string()),
+            header_var: Some("AUTH_HEADER".to_string()),
+            scopes: vec![],
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+        }
+        .execute(context)
+        .await
+    }
+
+    #[tokio::test]
+    async fn exec_injects_tokens_into_environment() {
+        let result = run(
+            r#"test "$TOKEN" = token123 && test "$REFRESH" = refresh123 \
+                && test "$AUTH_HEADER" = "Bearer token123""#,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn exec_forwards_exit_code() {
+        let result = run("exit 42").await;
+
+        assert!(matches!(result, Err(TokensError::ChildExited(42))));
+    }
+
+    #[tokio::test]
+    async fn exec_reports_signal_as_shell_exit_code() {
+        let result = run("kill -TERM $$").await;
+
+        assert!(matches!(result, Err(TokensError::ChildExited(143))));
+    }
+
+    /// Ctrl-C at a terminal signals the whole foreground process group, which the
+    /// child shares, so forwarding it as well would deliver it twice.
+    #[tokio::test]
+    async fn exec_delivers_terminal_interrupt_once() {
+        use std::os::fd::{FromRawFd, OwnedFd};
+        use std::os::unix::process::CommandExt;
+        use std::time::{Duration, Instant};
+
+        const HELPER_ENV: &str = "TOKENS_TEST_TERMINAL_HELPER";
+
+        // Re-run as the process in the foreground of the test's terminal.
+        if let Ok(dir) = std::env::var(HELPER_ENV) {
+            let script = r#"trap 'echo int >> "$1/interrupts"' INT; touch "$1/ready"
+                sleep 1 & wait; sleep 1 & wait"#;
+            let child = Command::new("sh")
+                .args(["-c", script, "sh", &dir])
+                .spawn()
+                .unwrap();
+            wait_forwarding_signals(child).await.unwrap();
+            return;
+        }
+
+        let (mut master, mut slave) = (-1, -1);
+        // SAFETY: both out-pointers are valid and the rest may be null.
+        let opened = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        assert_eq!(opened, 0, "openpty failed");
+        // SAFETY: openpty returned two fresh descriptors that nothing else owns.
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+        let dir = tempdir().unwrap();
+        let mut helper = std::process::Command::new(std::env::current_exe().unwrap());
+        helper
+            .args([
+                "commands::exec::tests::exec_delivers_terminal_interrupt_once",
+                "--exact",
+            ])
+            .env(HELPER_ENV, dir.path())
+            .stdin(slave.try_clone().unwrap())
+            .stdout(slave.try_clone().unwrap())
+            .stderr(slave);
+        // SAFETY: setsid and ioctl are async-signal-safe.
+        unsafe {
+            helper.pre_exec(|| {
+                // Start a session with the terminal as its controlling terminal.
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut helper = helper.spawn().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !dir.path().join("ready").exists() {
+            assert!(Instant::now() < deadline, "helper never became ready");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        std::fs::File::from(master).write_all(b"\x03").unwrap();
+
+        assert!(helper.wait().unwrap().success());
+        let interrupts = std::fs::read_to_string(dir.path().join("interrupts")).unwrap();
+        assert_eq!(interrupts.lines().count(), 1);
+    }
+}
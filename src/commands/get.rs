@@ -1,67 +1,189 @@
-use super::{CommandContext, CommandHandler, Format};
-use serde_json::json;
-use std::error::Error;
+use super::{CommandContext, CommandHandler, Format, acquire_token, token_expiry, verify_token};
+use crate::jwt::Jwt;
+use crate::template::{self, TemplateSource};
+use serde_json::{Value, json};
+use tokens::TokensError;
+use tokens::{DEFAULT_HOST_USERNAME, unix_now};
 
 pub struct GetCommand {
     pub nickname: String,
     pub refresh_token: bool,
     pub format: Option<Format>,
     pub scopes: Vec<String>,
+    pub verify: bool,
+    /// Variable name used by the `export` and `dotenv` formats.
+    pub env_var: String,
+    /// Host the `netrc` format writes an entry for.
+    pub machine: Option<String>,
+    /// Render the output from a template instead of `format`.
+    pub template: Option<TemplateSource>,
 }
 
 impl CommandHandler for GetCommand {
-    async fn execute(&self, context: CommandContext<'_>) -> Result<(), Box<dyn Error>> {
-        if let Some(auth) = context
-            .config_manager
-            .get_client_mut(context.config, &self.nickname)
-        {
-            let credentials_provider = context.credentials_provider;
-
-            match context
-                .token_manager
-                .get_or_refresh_token(auth, self.refresh_token, &self.scopes, credentials_provider)
+    async fn execute(&self, mut context: CommandContext<'_>) -> Result<(), TokensError> {
+        let token = acquire_token(
+            &mut context,
+            &self.nickname,
+            self.refresh_token,
+            &self.scopes,
+        )
+        .await?;
+
+        // A token that fails verification is never printed, so scripts can rely on
+        // the exit code.
+        if self.verify {
+            let checks = verify_token(&mut context, &self.nickname, &token)
                 .await
-            {
-                Ok(token) => {
-                    let msg = if let Some(ref format) = self.format {
-                        match format {
-                            Format::Header => json!({
-                                "Authorization": format!("Bearer {token}")
-                            })
-                            .to_string(),
-                        }
-                    } else {
-                        token
-                    };
-                    println!("{msg}");
-
-                    let config_path = context.config_manager.get_config_path();
-                    if let Err(err) = context
-                        .config_manager
-                        .save_config(&config_path, context.config)
-                    {
-                        eprintln!("Warning: token retrieved but failed to save config: {err}");
-                    }
-                }
-                Err(err) => {
-                    eprintln!("Failed to retrieve token: {err}");
-                }
+                .map_err(|err| err.context("Failed to verify token"))?;
+            checks.iter().for_each(|check| eprintln!("{check}"));
+
+            let failed: Vec<_> = checks
+                .iter()
+                .filter(|check| !check.passed)
+                .map(|check| check.name)
+                .collect();
+            if !failed.is_empty() {
+                return Err(format!("Token failed verification: {}.", failed.join(", ")).into());
             }
+        }
+
+        let msg = if let Some(ref source) = self.template {
+            self.render_template(&context, source, &token)?
+        } else if let Some(ref format) = self.format {
+            self.render(&context, format, &token)?
         } else {
-            eprintln!("Client '{}' not found.", self.nickname);
+            token
+        };
+        // Templates read from files usually end with their own newline.
+        if msg.ends_with('\n') {
+            print!("{msg}");
+        } else {
+            println!("{msg}");
         }
         Ok(())
     }
 }
 
+impl GetCommand {
+    fn render(
+        &self,
+        context: &CommandContext<'_>,
+        format: &Format,
+        token: &str,
+    ) -> Result<String, TokensError> {
+        let msg = match format {
+            Format::Header => json!({
+                "Authorization": format!("Bearer {token}")
+            })
+            .to_string(),
+            Format::Export => format!("export {}={}", self.env_var, shell_quote(token)),
+            Format::Dotenv => format!("{}={}", self.env_var, shell_quote(token)),
+            Format::Json => serde_json::to_string_pretty(&self.token_response(context, token))?,
+            Format::Curl => format!(
+                "-H {}",
+                shell_quote(&format!("Authorization: Bearer {token}"))
+            ),
+            Format::Netrc => {
+                let machine = self
+                    .machine
+                    .as_deref()
+                    .ok_or("The netrc format needs a host, given with --machine.")?;
+                format!("machine {machine} login {DEFAULT_HOST_USERNAME} password {token}")
+            }
+        };
+        Ok(msg)
+    }
+
+    /// Fill in a template with the tokens, the client's details and the access
+    /// token's claims.
+    fn render_template(
+        &self,
+        context: &CommandContext<'_>,
+        source: &TemplateSource,
+        token: &str,
+    ) -> Result<String, TokensError> {
+        let template = source.load(context.config)?;
+        let client = context.config.clients.get(&self.nickname);
+
+        let (access_token, refresh_token) = if self.refresh_token {
+            let cached = client.and_then(|client| client.access_token.as_ref());
+            (
+                cached.map(|cached| cached.access_token.clone()),
+                Some(token.to_string()),
+            )
+        } else {
+            (
+                Some(token.to_string()),
+                client.and_then(|client| client.refresh_token.clone()),
+            )
+        };
+        let expires_at = access_token
+            .as_deref()
+            .and_then(|token| token_expiry(context, &self.nickname, token));
+        let claims = access_token
+            .as_deref()
+            .and_then(|token| Jwt::decode(token).ok())
+            .map(|jwt| jwt.claims);
+
+        template::render(&template, |name| match name {
+            "access_token" => access_token.clone(),
+            "refresh_token" => refresh_token.clone(),
+            "expires_at" => expires_at.map(|expires_at| expires_at.to_string()),
+            "nickname" => Some(self.nickname.clone()),
+            "client_id" => client.map(|client| client.client_id.clone()),
+            name => template::claim(claims.as_ref()?, name.strip_prefix("claims.")?),
+        })
+    }
+
+    /// The token as the token endpoint returned it, rebuilt from the stored client
+    /// since the response may have been cached by an earlier run.
+    fn token_response(&self, context: &CommandContext<'_>, token: &str) -> Value {
+        if self.refresh_token {
+            return json!({ "refresh_token": token });
+        }
+
+        let client = context.config.clients.get(&self.nickname);
+        let cached = client
+            .and_then(|client| client.access_token.as_ref())
+            .filter(|cached| cached.access_token == token);
+
+        let mut response = json!({ "access_token": token, "token_type": "Bearer" });
+        if let Some(expires_at) = token_expiry(context, &self.nickname, token) {
+            response["expires_in"] = expires_at.saturating_sub(unix_now()).into();
+        }
+        if let Some(refresh_token) = client.and_then(|client| client.refresh_token.as_ref()) {
+            response["refresh_token"] = refresh_token.as_str().into();
+        }
+        if let Some(id_token) = cached.and_then(|cached| cached.id_token.as_ref()) {
+            response["id_token"] = id_token.as_str().into();
+        }
+        let scope = cached.and_then(|cached| cached.scope.clone()).or_else(|| {
+            let scopes = Jwt::decode(token).ok()?.scopes();
+            (!scopes.is_empty()).then(|| scopes.join(" "))
+        });
+        if let Some(scope) = scope {
+            response["scope"] = scope.into();
+        }
+        response
+    }
+}
+
+/// Quote `value` for a POSIX shell, unless it is made only of characters that are
+/// safe unquoted, as JWTs are.
+fn shell_quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_.~+/=:@%,".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{
-        config::ConfigManager,
-        oauth::TokenManager,
-        types::{AuthConfig, CredentialsProvider},
-    };
     use httpmock::{Method::POST, MockServer};
+    use tempfile::tempdir;
+    use tokens::{AuthConfig, ConfigManager, CredentialsProvider, TokenManager};
 
     struct MockCredentialsProvider;
 
@@ -75,17 +197,19 @@ mod tests {
         use super::*;
         use crate::commands::get::GetCommand;
         use crate::commands::{CommandContext, CommandHandler, Format};
-        use crate::types::ConfigFile;
         use std::collections::HashMap;
+        use tokens::{CachedToken, ConfigFile, unix_now};
         use tokio;
 
         #[tokio::test]
         async fn test_get_command_client_not_found() {
             let config = ConfigFile {
                 clients: HashMap::new(),
+                ..Default::default()
             };
             let mut config = config;
-            let config_manager = ConfigManager::new();
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
             let token_manager = TokenManager::new();
             let mock_credentials_provider = MockCredentialsProvider;
 
@@ -101,10 +225,17 @@ mod tests {
                 refresh_token: false,
                 format: None,
                 scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
             };
 
             let result = get_command.execute(context).await;
-            assert!(result.is_ok());
+            assert!(matches!(
+                result,
+                Err(tokens::TokensError::ClientNotFound(_))
+            ));
         }
 
         #[tokio::test]
@@ -131,11 +262,16 @@ mod tests {
                     client_id: "test-client".into(),
                     refresh_token: Some("existing_refresh_token".into()),
                     secret: None,
+                    ..Default::default()
                 },
             );
 
-            let mut config = ConfigFile { clients };
-            let config_manager = ConfigManager::new();
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
             let token_manager = TokenManager::new();
             let mock_credentials_provider = MockCredentialsProvider;
 
@@ -151,6 +287,10 @@ mod tests {
                 refresh_token: false,
                 format: None,
                 scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
             };
 
             let result = get_command.execute(context).await;
@@ -181,11 +321,16 @@ mod tests {
                     client_id: "test-client".into(),
                     refresh_token: Some("existing_refresh_token".into()),
                     secret: None,
+                    ..Default::default()
                 },
             );
 
-            let mut config = ConfigFile { clients };
-            let config_manager = ConfigManager::new();
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
             let token_manager = TokenManager::new();
             let mock_credentials_provider = MockCredentialsProvider;
 
@@ -201,12 +346,73 @@ mod tests {
                 refresh_token: false,
                 format: Some(Format::Header),
                 scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
             };
 
             let result = get_command.execute(context).await;
             assert!(result.is_ok());
         }
 
+        #[tokio::test]
+        async fn test_get_command_uses_cached_token() {
+            let server = MockServer::start();
+
+            let mock = server.mock(|when, then| {
+                when.method(POST).path("/protocol/openid-connect/token");
+                then.status(500);
+            });
+
+            let mut clients = HashMap::new();
+            clients.insert(
+                "test_client".into(),
+                AuthConfig {
+                    auth_url: server.url(""),
+                    client_id: "test-client".into(),
+                    refresh_token: Some("existing_refresh_token".into()),
+                    access_token: Some(CachedToken {
+                        access_token: "cached_access_token".into(),
+                        expires_at: unix_now() + 300,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            );
+
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+            let token_manager = TokenManager::new();
+            let mock_credentials_provider = MockCredentialsProvider;
+
+            let context = CommandContext {
+                config: &mut config,
+                config_manager: &config_manager,
+                token_manager: &token_manager,
+                credentials_provider: &mock_credentials_provider,
+            };
+
+            let get_command = GetCommand {
+                nickname: "test_client".to_string(),
+                refresh_token: false,
+                format: None,
+                scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
+            };
+
+            let result = get_command.execute(context).await;
+            assert!(result.is_ok());
+            mock.assert_hits(0);
+        }
+
         #[tokio::test]
         async fn test_get_command_token_failure() {
             let server = MockServer::start();
@@ -224,11 +430,16 @@ mod tests {
                     client_id: "test-client".into(),
                     refresh_token: Some("invalid_refresh_token".into()),
                     secret: None,
+                    ..Default::default()
                 },
             );
 
-            let mut config = ConfigFile { clients };
-            let config_manager = ConfigManager::new();
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
             let token_manager = TokenManager::new();
             let mock_credentials_provider = MockCredentialsProvider;
 
@@ -244,10 +455,14 @@ mod tests {
                 refresh_token: false,
                 format: None,
                 scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
             };
 
             let result = get_command.execute(context).await;
-            assert!(result.is_ok());
+            assert!(matches!(result, Err(tokens::TokensError::InvalidGrant(_))));
         }
 
         #[tokio::test]
@@ -276,11 +491,16 @@ mod tests {
                     client_id: "test-client".into(),
                     refresh_token: Some("existing_refresh_token".into()),
                     secret: None,
+                    ..Default::default()
                 },
             );
 
-            let mut config = ConfigFile { clients };
-            let config_manager = ConfigManager::new();
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
             let token_manager = TokenManager::new();
             let mock_credentials_provider = MockCredentialsProvider;
 
@@ -296,10 +516,156 @@ mod tests {
                 refresh_token: true,
                 format: None,
                 scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
+            };
+
+            let result = get_command.execute(context).await;
+            assert!(result.is_ok());
+        }
+
+        #[tokio::test]
+        async fn test_get_command_uses_refresh_token_rotated_on_disk() {
+            let server = MockServer::start();
+
+            let mock = server.mock(|when, then| {
+                when.method(POST)
+                    .path("/protocol/openid-connect/token")
+                    .body_contains("refresh_token=rotated_refresh_token");
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .json_body(serde_json::json!({
+                        "access_token": "test_access_token",
+                        "refresh_token": "next_refresh_token"
+                    }));
+            });
+
+            let client = |refresh_token: &str| AuthConfig {
+                auth_url: server.url(""),
+                client_id: "test-client".into(),
+                refresh_token: Some(refresh_token.into()),
+                ..Default::default()
+            };
+
+            let dir = tempdir().unwrap();
+            let config_path = dir.path().join("config.json");
+            let config_manager = ConfigManager::with_path(config_path.clone());
+
+            let mut on_disk = ConfigFile::default();
+            on_disk
+                .clients
+                .insert("test_client".into(), client("rotated_refresh_token"));
+            config_manager.save_config(&config_path, &on_disk).unwrap();
+
+            let mut config = ConfigFile::default();
+            config
+                .clients
+                .insert("test_client".into(), client("stale_refresh_token"));
+            let token_manager = TokenManager::new();
+            let mock_credentials_provider = MockCredentialsProvider;
+
+            let context = CommandContext {
+                config: &mut config,
+                config_manager: &config_manager,
+                token_manager: &token_manager,
+                credentials_provider: &mock_credentials_provider,
+            };
+
+            let get_command = GetCommand {
+                nickname: "test_client".to_string(),
+                refresh_token: false,
+                format: None,
+                scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
             };
 
             let result = get_command.execute(context).await;
             assert!(result.is_ok());
+            mock.assert();
+
+            let saved = config_manager.read_config(&config_path).unwrap().unwrap();
+            assert_eq!(
+                saved.clients["test_client"].refresh_token.as_deref(),
+                Some("next_refresh_token")
+            );
+        }
+
+        #[tokio::test]
+        async fn test_get_command_logs_in_without_holding_the_config_lock() {
+            struct LockCheckingCredentialsProvider(std::path::PathBuf);
+
+            impl CredentialsProvider for LockCheckingCredentialsProvider {
+                fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+                    let lock_file = std::fs::File::open(&self.0)?;
+                    lock_file
+                        .try_lock()
+                        .map_err(|e| format!("config lock held during login: {e}"))?;
+                    Ok(("user".into(), "pass".into()))
+                }
+            }
+
+            let server = MockServer::start();
+            let mock = server.mock(|when, then| {
+                when.method(POST)
+                    .path("/protocol/openid-connect/token")
+                    .body_contains("grant_type=password");
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .json_body(serde_json::json!({
+                        "access_token": "test_access_token",
+                        "refresh_token": "test_refresh_token"
+                    }));
+            });
+
+            let dir = tempdir().unwrap();
+            let config_path = dir.path().join("config.json");
+            let config_manager = ConfigManager::with_path(config_path.clone());
+            let mut config = ConfigFile::default();
+            config.clients.insert(
+                "test_client".into(),
+                AuthConfig {
+                    auth_url: server.url(""),
+                    client_id: "test-client".into(),
+                    ..Default::default()
+                },
+            );
+            config_manager.save_config(&config_path, &config).unwrap();
+
+            let token_manager = TokenManager::new();
+            let credentials_provider =
+                LockCheckingCredentialsProvider(dir.path().join("config.json.lock"));
+            let context = CommandContext {
+                config: &mut config,
+                config_manager: &config_manager,
+                token_manager: &token_manager,
+                credentials_provider: &credentials_provider,
+            };
+
+            let get_command = GetCommand {
+                nickname: "test_client".to_string(),
+                refresh_token: false,
+                format: None,
+                scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
+            };
+
+            let result = get_command.execute(context).await;
+            assert!(result.is_ok(), "{result:?}");
+            mock.assert();
+
+            let saved = config_manager.read_config(&config_path).unwrap().unwrap();
+            assert_eq!(
+                saved.clients["test_client"].refresh_token.as_deref(),
+                Some("test_refresh_token")
+            );
         }
 
         #[tokio::test]
@@ -326,11 +692,16 @@ mod tests {
                     client_id: "test-client".into(),
                     refresh_token: Some("existing_refresh_token".into()),
                     secret: None,
+                    ..Default::default()
                 },
             );
 
-            let mut config = ConfigFile { clients };
-            let config_manager = ConfigManager::new();
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
             let token_manager = TokenManager::new();
             let mock_credentials_provider = MockCredentialsProvider;
 
@@ -346,10 +717,207 @@ mod tests {
                 refresh_token: false,
                 format: None,
                 scopes: vec!["read".to_string(), "write".to_string()],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
             };
 
             let result = get_command.execute(context).await;
             assert!(result.is_ok());
         }
+
+        #[tokio::test]
+        async fn test_get_command_verify_fails_on_bad_token() {
+            let server = MockServer::start();
+            let _certs = server.mock(|when, then| {
+                when.path("/protocol/openid-connect/certs");
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .body(r#"{"keys": []}"#);
+            });
+
+            let mut clients = HashMap::new();
+            clients.insert(
+                "test_client".into(),
+                AuthConfig {
+                    auth_url: server.url(""),
+                    client_id: "test-client".into(),
+                    access_token: Some(CachedToken {
+                        access_token: "forged".into(),
+                        expires_at: unix_now() + 3600,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            );
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+            let token_manager = TokenManager::new();
+            let context = CommandContext {
+                config: &mut config,
+                config_manager: &config_manager,
+                token_manager: &token_manager,
+                credentials_provider: &MockCredentialsProvider,
+            };
+
+            let result = GetCommand {
+                nickname: "test_client".to_string(),
+                refresh_token: false,
+                format: None,
+                scopes: vec![],
+                verify: true,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
+            }
+            .execute(context)
+            .await;
+
+            let err = result.unwrap_err();
+            assert_eq!(err.exit_code(), 1);
+            assert!(err.to_string().contains("failed verification"), "{err}");
+        }
+
+        #[test]
+        fn test_get_command_render_formats() {
+            let mut clients = HashMap::new();
+            clients.insert(
+                "test_client".into(),
+                AuthConfig {
+                    auth_url: "http://127.0.0.1:1".into(),
+                    client_id: "test-client".into(),
+                    refresh_token: Some("refresh".into()),
+                    access_token: Some(CachedToken {
+                        access_token: "access".into(),
+                        expires_at: unix_now() + 300,
+                        id_token: Some("id".into()),
+                        scope: Some("openid profile".into()),
+                    }),
+                    ..Default::default()
+                },
+            );
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+            let token_manager = TokenManager::new();
+            let context = CommandContext {
+                config: &mut config,
+                config_manager: &config_manager,
+                token_manager: &token_manager,
+                credentials_provider: &MockCredentialsProvider,
+            };
+
+            let get_command = GetCommand {
+                nickname: "test_client".to_string(),
+                refresh_token: false,
+                format: None,
+                scopes: vec![],
+                verify: false,
+                env_var: "API_TOKEN".to_string(),
+                machine: Some("api.internal".to_string()),
+                template: None,
+            };
+            let render = |format| get_command.render(&context, &format, "access").unwrap();
+
+            assert_eq!(render(Format::Export), "export API_TOKEN=access");
+            assert_eq!(render(Format::Dotenv), "API_TOKEN=access");
+            assert_eq!(
+                get_command
+                    .render(&context, &Format::Dotenv, "a b'c")
+                    .unwrap(),
+                r"API_TOKEN='a b'\''c'"
+            );
+            assert_eq!(render(Format::Curl), "-H 'Authorization: Bearer access'");
+            assert_eq!(
+                render(Format::Netrc),
+                "machine api.internal login oauth2 password access"
+            );
+
+            let json: serde_json::Value = serde_json::from_str(&render(Format::Json)).unwrap();
+            assert_eq!(json["access_token"], "access");
+            assert_eq!(json["refresh_token"], "refresh");
+            assert_eq!(json["id_token"], "id");
+            assert_eq!(json["scope"], "openid profile");
+            assert!(json["expires_in"].as_u64().is_some_and(|secs| secs <= 300));
+        }
+
+        #[test]
+        fn test_get_command_render_template() {
+            use crate::template::TemplateSource;
+            use base64::Engine;
+            use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+            let token = format!(
+                "{}.{}.sig",
+                URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+                URL_SAFE_NO_PAD.encode(r#"{"sub":"user-1","realm_access":{"roles":["dev"]}}"#)
+            );
+            let mut clients = HashMap::new();
+            clients.insert(
+                "test_client".into(),
+                AuthConfig {
+                    auth_url: "http://127.0.0.1:1".into(),
+                    client_id: "test-client".into(),
+                    refresh_token: Some("refresh".into()),
+                    access_token: Some(CachedToken {
+                        access_token: token.clone(),
+                        expires_at: 4_000_000_000,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            );
+            let mut config = ConfigFile {
+                clients,
+                ..Default::default()
+            };
+            config
+                .templates
+                .insert("ids".into(), "{{nickname}}/{{client_id}}".into());
+            let dir = tempdir().unwrap();
+            let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+            let token_manager = TokenManager::new();
+            let context = CommandContext {
+                config: &mut config,
+                config_manager: &config_manager,
+                token_manager: &token_manager,
+                credentials_provider: &MockCredentialsProvider,
+            };
+
+            let get_command = GetCommand {
+                nickname: "test_client".to_string(),
+                refresh_token: false,
+                format: None,
+                scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
+            };
+            let render = |source| get_command.render_template(&context, &source, &token);
+
+            assert_eq!(
+                render(TemplateSource::Inline(
+                    "{{ claims.sub }} {{claims.realm_access.roles.0}} {{expires_at}} {{refresh_token}}"
+                        .into()
+                ))
+                .unwrap(),
+                "user-1 dev 4000000000 refresh"
+            );
+            assert_eq!(
+                render(TemplateSource::Named("ids".into())).unwrap(),
+                "test_client/test-client"
+            );
+            assert!(render(TemplateSource::Inline("{{claims.missing}}".into())).is_err());
+            assert!(render(TemplateSource::Named("missing".into())).is_err());
+        }
     }
 }
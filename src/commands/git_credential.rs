@@ -0,0 +1,252 @@
+use super::{
+    CommandContext, CommandHandler, HostTable, acquire_token, invalidate_token, map_host,
+    token_expiry, unmap_host,
+};
+use clap::Subcommand;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use tokens::TokensError;
+use tokens::{ConfigFile, DEFAULT_HOST_USERNAME, HostMapping};
+
+const GIT_HOSTS: HostTable = |config| &mut config.git_hosts;
+
+#[derive(Debug, Subcommand)]
+pub enum GitCredentialAction {
+    /// Answer git's request for credentials.
+    Get,
+    /// Sent by git when the credentials worked. Tokens are already stored, so this does nothing.
+    Store,
+    /// Sent by git when the credentials were rejected. Forgets the cached access token.
+    Erase,
+    /// Answer for a host, given as `host[:port]` or `protocol://host[:port]`, with a stored client.
+    Map {
+        host: String,
+        nickname: String,
+        /// Username to send with the token.
+        #[arg(short, long)]
+        username: Option<String>,
+    },
+    /// Stop answering for a host.
+    Unmap { host: String },
+}
+
+pub struct GitCredentialCommand {
+    pub action: GitCredentialAction,
+}
+
+impl CommandHandler for GitCredentialCommand {
+    async fn execute(&self, mut context: CommandContext<'_>) -> Result<(), TokensError> {
+        match &self.action {
+            GitCredentialAction::Map {
+                host,
+                nickname,
+                username,
+            } => {
+                let mapping = HostMapping {
+                    nickname: nickname.clone(),
+                    username: username.clone(),
+                };
+                map_host(&mut context, GIT_HOSTS, host, mapping)?;
+                println!("Git credentials for '{host}' now come from '{nickname}'.");
+            }
+            GitCredentialAction::Unmap { host } => {
+                unmap_host(&mut context, GIT_HOSTS, host)?;
+                println!("Git credentials for '{host}' are no longer provided.");
+            }
+            action => {
+                let mut input = String::new();
+                io::stdin().read_to_string(&mut input)?;
+                print!("{}", respond(&mut context, action, &input).await?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Handle one `get`, `store` or `erase` request from git, returning the reply.
+/// Hosts without a mapping get an empty reply, so git moves on to its next helper.
+async fn respond(
+    context: &mut CommandContext<'_>,
+    action: &GitCredentialAction,
+    input: &str,
+) -> Result<String, TokensError> {
+    let attributes = parse_attributes(input);
+    let Some(mapping) = find_mapping(context.config, &attributes).cloned() else {
+        return Ok(String::new());
+    };
+
+    match action {
+        GitCredentialAction::Get => {
+            let token = acquire_token(context, &mapping.nickname, false, &[]).await?;
+            let username = mapping
+                .username
+                .as_deref()
+                .or(attributes.get("username").map(String::as_str))
+                .unwrap_or(DEFAULT_HOST_USERNAME);
+
+            let mut reply = format!("username={username}\npassword={token}\n");
+            if let Some(expires_at) = token_expiry(context, &mapping.nickname, &token) {
+                let expiry =
+                    expires_at.saturating_sub(context.token_manager.expiry_skew().as_secs());
+                reply.push_str(&format!("password_expiry_utc={expiry}\n"));
+            }
+            Ok(reply)
+        }
+        GitCredentialAction::Erase => {
+            if let Some(token) = attributes.get("password") {
+                invalidate_token(context, &mapping.nickname, token)?;
+            }
+            Ok(String::new())
+        }
+        _ => Ok(String::new()),
+    }
+}
+
+/// Parse git's `key=value` lines, stopping at the first blank line.
+fn parse_attributes(input: &str) -> HashMap<String, String> {
+    input
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// The mapping for `protocol://host`, falling back to one for the bare host.
+fn find_mapping<'a>(
+    config: &'a ConfigFile,
+    attributes: &HashMap<String, String>,
+) -> Option<&'a HostMapping> {
+    let host = attributes.get("host")?;
+    attributes
+        .get("protocol")
+        .and_then(|protocol| config.git_hosts.get(&format!("{protocol}://{host}")))
+        .or_else(|| config.git_hosts.get(host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use tokens::ConfigManager;
+    use tokens::TokenManager;
+    use tokens::{AuthConfig, CachedToken, CredentialsProvider};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
+    const EXPIRES_AT: u64 = 4_000_000_000;
+
+    fn git_config() -> ConfigFile {
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "git".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "git".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "cached".to_string(),
+                    expires_at: EXPIRES_AT,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        config.git_hosts.insert(
+            "https://git.internal".to_string(),
+            HostMapping {
+                nickname: "git".to_string(),
+                username: None,
+            },
+        );
+        config
+    }
+
+    async fn run(
+        config: &mut ConfigFile,
+        action: GitCredentialAction,
+        input: &str,
+    ) -> Result<String, TokensError> {
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let mut context = CommandContext {
+            config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &MockCredentialsProvider,
+        };
+        respond(&mut context, &action, input).await
+    }
+
+    #[tokio::test]
+    async fn get_answers_with_the_mapped_clients_token() {
+        let mut config = git_config();
+
+        let reply = run(
+            &mut config,
+            GitCredentialAction::Get,
+            "protocol=https\nhost=git.internal\n\n",
+        )
+        .await
+        .unwrap();
+
+        let expiry = EXPIRES_AT - TokenManager::new().expiry_skew().as_secs();
+        assert_eq!(
+            reply,
+            format!("username=oauth2\npassword=cached\npassword_expiry_utc={expiry}\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn get_ignores_unmapped_hosts() {
+        let mut config = git_config();
+
+        let reply = run(
+            &mut config,
+            GitCredentialAction::Get,
+            "protocol=http\nhost=git.internal\n",
+        )
+        .await
+        .unwrap();
+
+        assert!(reply.is_empty());
+    }
+
+    #[tokio::test]
+    async fn erase_forgets_the_rejected_token() {
+        let mut config = git_config();
+
+        run(
+            &mut config,
+            GitCredentialAction::Erase,
+            "protocol=https\nhost=git.internal\nusername=oauth2\npassword=cached\n",
+        )
+        .await
+        .unwrap();
+
+        assert!(config.clients["git"].access_token.is_none());
+    }
+
+    #[test]
+    fn mapping_falls_back_to_bare_host() {
+        let mut config = ConfigFile::default();
+        let mapping = HostMapping {
+            nickname: "git".to_string(),
+            username: Some("me".to_string()),
+        };
+        config
+            .git_hosts
+            .insert("git.internal:8443".to_string(), mapping.clone());
+
+        let attributes = parse_attributes("protocol=https\nhost=git.internal:8443\n\nhost=other\n");
+
+        assert_eq!(find_mapping(&config, &attributes), Some(&mapping));
+    }
+}
@@ -0,0 +1,163 @@
+use super::{CommandContext, CommandHandler, acquire_token, verify_token};
+use crate::jwt::Jwt;
+use chrono::DateTime;
+use prettytable::format::consts::FORMAT_CLEAN;
+use prettytable::{Cell, Row, Table};
+use std::io;
+use tokens::TokensError;
+use tokens::unix_now;
+
+pub struct InspectCommand {
+    /// Client to fetch a token for. Reads a raw token from stdin when absent.
+    pub nickname: Option<String>,
+    pub scopes: Vec<String>,
+    /// Verify the token against the client's issuer.
+    pub verify: bool,
+}
+
+impl CommandHandler for InspectCommand {
+    async fn execute(&self, mut context: CommandContext<'_>) -> Result<(), TokensError> {
+        if self.verify && self.nickname.is_none() {
+            return Err("--verify needs a client nickname to know the expected issuer.".into());
+        }
+
+        let token = match &self.nickname {
+            Some(nickname) => acquire_token(&mut context, nickname, false, &self.scopes).await?,
+            None => io::read_to_string(io::stdin())?,
+        };
+
+        let jwt = Jwt::decode(&token)?;
+        println!("Header:\n{}", serde_json::to_string_pretty(&jwt.header)?);
+        println!(
+            "\nPayload:\n{}\n",
+            serde_json::to_string_pretty(&jwt.claims)?
+        );
+        summary_table(&jwt, unix_now() as i64).printstd();
+
+        if let (true, Some(nickname)) = (self.verify, &self.nickname) {
+            println!("\nVerification:");
+            for check in verify_token(&mut context, nickname, &token).await? {
+                println!("{check}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn summary_table(jwt: &Jwt, now: i64) -> Table {
+    let mut table = Table::new();
+    table.set_format(*FORMAT_CLEAN);
+
+    for (label, claim) in [("Issued", "iat"), ("Not before", "nbf"), ("Expires", "exp")] {
+        if let Some(timestamp) = jwt.timestamp(claim) {
+            table.add_row(Row::new(vec![
+                Cell::new(label).style_spec("b"),
+                Cell::new(&describe_time(timestamp, now)),
+            ]));
+        }
+    }
+
+    let mut highlight = |label: &str, values: Vec<String>| {
+        if !values.is_empty() {
+            table.add_row(Row::new(vec![
+                Cell::new(label).style_spec("b"),
+                Cell::new(&values.join(" ")).style_spec("bFg"),
+            ]));
+        }
+    };
+
+    highlight("Scopes", jwt.scopes());
+    highlight("Realm roles", jwt.realm_roles());
+    for (client, roles) in jwt.client_roles() {
+        highlight(&format!("Roles ({client})"), roles);
+    }
+
+    table
+}
+
+/// Render a Unix timestamp as a UTC date with the time until or since it.
+fn describe_time(timestamp: i64, now: i64) -> String {
+    let date = DateTime::from_timestamp(timestamp, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string());
+
+    let delta = timestamp - now;
+    if delta >= 0 {
+        format!("{date} (in {})", format_duration(delta.unsigned_abs()))
+    } else {
+        format!("{date} ({} ago)", format_duration(delta.unsigned_abs()))
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+    let mut remaining = secs;
+    let parts: Vec<String> = units
+        .iter()
+        .filter_map(|&(unit, size)| {
+            let value = remaining / size;
+            remaining %= size;
+            (value > 0).then(|| format!("{value}{unit}"))
+        })
+        .collect();
+
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use serde_json::json;
+
+    #[test]
+    fn test_describe_time() {
+        assert_eq!(
+            describe_time(1700000300, 1700000030),
+            "2023-11-14 22:18:20 UTC (in 4m 30s)"
+        );
+        assert_eq!(
+            describe_time(1700000000, 1700003661),
+            "2023-11-14 22:13:20 UTC (1h 1m 1s ago)"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(90061), "1d 1h 1m 1s");
+        assert_eq!(format_duration(3600), "1h");
+    }
+
+    #[test]
+    fn test_summary_table() {
+        let claims = json!({
+            "iat": 1700000000,
+            "exp": 1700000300,
+            "scope": "openid email",
+            "realm_access": {"roles": ["admin"]},
+            "resource_access": {"account": {"roles": ["manage-account"]}}
+        });
+        let token = format!(
+            "{}.{}.sig",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let jwt = Jwt::decode(&token).unwrap();
+
+        let table = summary_table(&jwt, 1700000100).to_string();
+
+        assert!(table.contains("Issued"));
+        assert!(table.contains("(in 3m 20s)"));
+        assert!(table.contains("openid email"));
+        assert!(table.contains("admin"));
+        assert!(table.contains("Roles (account)"));
+        assert!(!table.contains("Not before"));
+    }
+}
@@ -0,0 +1,122 @@
+use super::{CommandContext, CommandHandler, acquire_token, token_expiry};
+use chrono::{DateTime, SecondsFormat};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokens::TokensError;
+
+pub struct KubeCredentialCommand {
+    pub nickname: String,
+    pub scopes: Vec<String>,
+}
+
+impl CommandHandler for KubeCredentialCommand {
+    async fn execute(&self, mut context: CommandContext<'_>) -> Result<(), TokensError> {
+        let token = acquire_token(&mut context, &self.nickname, false, &self.scopes).await?;
+        let expires_at = token_expiry(&context, &self.nickname, &token);
+
+        let credential = exec_credential(&token, expires_at, context.token_manager.expiry_skew());
+        println!("{}", serde_json::to_string_pretty(&credential)?);
+        Ok(())
+    }
+}
+
+/// An `ExecCredential` as read by kubectl from exec credential plugins.
+///
+/// The expiry is brought forward by `skew` so that kubectl asks again at the same
+/// point this tool would stop reusing the cached token.
+fn exec_credential(token: &str, expires_at: Option<u64>, skew: Duration) -> Value {
+    let mut status = json!({ "token": token });
+    if let Some(expiry) = expires_at
+        .map(|expires_at| expires_at.saturating_sub(skew.as_secs()))
+        .and_then(|expiry| DateTime::from_timestamp(expiry as i64, 0))
+    {
+        status["expirationTimestamp"] = expiry.to_rfc3339_opts(SecondsFormat::Secs, true).into();
+    }
+
+    json!({
+        "apiVersion": "client.authentication.k8s.io/v1",
+        "kind": "ExecCredential",
+        "status": status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use tokens::ConfigManager;
+    use tokens::TokenManager;
+    use tokens::{AuthConfig, CachedToken, ConfigFile, CredentialsProvider, unix_now};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
+    #[test]
+    fn exec_credential_reports_expiry_less_skew() {
+        let credential = exec_credential("abc", Some(1_700_000_060), Duration::from_secs(60));
+
+        assert_eq!(
+            credential,
+            json!({
+                "apiVersion": "client.authentication.k8s.io/v1",
+                "kind": "ExecCredential",
+                "status": {
+                    "token": "abc",
+                    "expirationTimestamp": "2023-11-14T22:13:20Z",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn exec_credential_omits_unknown_expiry() {
+        let credential = exec_credential("abc", None, Duration::from_secs(60));
+
+        assert_eq!(credential["status"], json!({ "token": "abc" }));
+    }
+
+    #[tokio::test]
+    async fn kube_credential_uses_cached_token() {
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "cluster".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "kubernetes".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "cached".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.json");
+        let config_manager = ConfigManager::with_path(config_path.clone());
+        let token_manager = TokenManager::new();
+        let context = CommandContext {
+            config: &mut config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &MockCredentialsProvider,
+        };
+
+        let result = KubeCredentialCommand {
+            nickname: "cluster".to_string(),
+            scopes: vec![],
+        }
+        .execute(context)
+        .await;
+
+        assert!(result.is_ok(), "{result:?}");
+        // Reusing the cached token must not rewrite the config.
+        assert!(!config_path.exists());
+    }
+}
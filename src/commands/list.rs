@@ -1,11 +1,11 @@
 use super::{CommandContext, CommandHandler};
 use prettytable::{Table, row};
-use std::error::Error;
+use tokens::TokensError;
 
 pub struct ListCommand;
 
 impl CommandHandler for ListCommand {
-    async fn execute(&self, context: CommandContext<'_>) -> Result<(), Box<dyn Error>> {
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError> {
         let mut table = Table::new();
         table.add_row(row!["Nickname", "ClientId", "URL"]);
 
@@ -24,12 +24,8 @@ mod tests {
 
     use prettytable::Table;
 
-    use crate::{
-        commands::{CommandContext, CommandHandler, list::ListCommand},
-        config::ConfigManager,
-        oauth::TokenManager,
-        types::{AuthConfig, ConfigFile, CredentialsProvider},
-    };
+    use crate::commands::{CommandContext, CommandHandler, list::ListCommand};
+    use tokens::{AuthConfig, ConfigFile, ConfigManager, CredentialsProvider, TokenManager};
 
     struct MockCredentialsProvider;
 
@@ -50,6 +46,7 @@ mod tests {
                         client_id: "client_id_1".to_string(),
                         refresh_token: Some("token1".to_string()),
                         secret: None,
+                        ..Default::default()
                     },
                 ),
                 (
@@ -59,11 +56,13 @@ mod tests {
                         client_id: "client_id_2".to_string(),
                         refresh_token: None,
                         secret: None,
+                        ..Default::default()
                     },
                 ),
             ]
             .into_iter()
             .collect(),
+            ..Default::default()
         };
 
         let config_manager = ConfigManager::new();
@@ -124,6 +123,7 @@ mod tests {
                         client_id: "id1".to_string(),
                         refresh_token: Some("token1".to_string()),
                         secret: None,
+                        ..Default::default()
                     },
                 );
                 clients.insert(
@@ -133,10 +133,12 @@ mod tests {
                         client_id: "id2".to_string(),
                         refresh_token: None,
                         secret: None,
+                        ..Default::default()
                     },
                 );
                 clients
             },
+            ..Default::default()
         };
 
         let list_command = ListCommand;
@@ -1,28 +1,47 @@
 use super::{CommandContext, CommandHandler};
-use std::error::Error;
+use tokens::TokensError;
 
 pub struct LogoutCommand {
     pub nickname: String,
+    pub local: bool,
 }
 
 impl CommandHandler for LogoutCommand {
-    async fn execute(&self, context: CommandContext<'_>) -> Result<(), Box<dyn Error>> {
-        if let Some(client) = context
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError> {
+        // Hold the lock until the tokens are cleared, and revoke the latest refresh
+        // token rather than one another process may since have rotated.
+        let config_path = context.config_manager.get_config_path();
+        let lock = context.config_manager.lock_async(&config_path).await?;
+        if let Some(latest) = lock
+            .read()?
+            .and_then(|mut config| config.clients.remove(&self.nickname))
+        {
+            context.config.clients.insert(self.nickname.clone(), latest);
+        }
+
+        let client = context
             .config_manager
             .get_client_mut(context.config, &self.nickname)
+            .ok_or_else(|| TokensError::ClientNotFound(self.nickname.clone()))?;
+
+        if !self.local
+            && let Err(e) = context.token_manager.revoke_refresh_token(client).await
         {
-            client.refresh_token = None;
-            let config_path = context.config_manager.get_config_path();
-            match context
-                .config_manager
-                .save_config(&config_path, context.config)
-            {
-                Ok(()) => println!("Refresh token for '{}' removed", self.nickname),
-                Err(e) => println!("Failed to update config file.\n{e}"),
-            }
-        } else {
-            println!("Client '{}' doesn't exist", self.nickname);
+            eprintln!(
+                "Failed to revoke the session for '{}' at the server. The refresh token was kept. Use --local to remove it without revoking.",
+                self.nickname
+            );
+            return Err(e.into());
         }
+
+        client.clear_tokens();
+        lock.update(|config| {
+            if let Some(client) = config.clients.get_mut(&self.nickname) {
+                client.clear_tokens();
+            }
+        })
+        .map_err(|e| TokensError::from(e).context("Failed to update config file"))?;
+        println!("Refresh token for '{}' removed", self.nickname);
         Ok(())
     }
 }
@@ -30,12 +49,12 @@ impl CommandHandler for LogoutCommand {
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use tempfile::tempdir;
 
-    use crate::{
-        commands::{CommandContext, CommandHandler, logout::LogoutCommand},
-        config::ConfigManager,
-        oauth::TokenManager,
-        types::{AuthConfig, ConfigFile, CredentialsProvider},
+    use crate::commands::{CommandContext, CommandHandler, logout::LogoutCommand};
+    use httpmock::{Method::POST, MockServer};
+    use tokens::{
+        AuthConfig, ConfigFile, ConfigManager, CredentialsProvider, TokenManager, TokensError,
     };
 
     struct MockCredentialsProvider;
@@ -57,6 +76,7 @@ mod tests {
                         client_id: "client_id_1".to_string(),
                         refresh_token: Some("token1".to_string()),
                         secret: None,
+                        ..Default::default()
                     },
                 ),
                 (
@@ -66,11 +86,13 @@ mod tests {
                         client_id: "client_id_2".to_string(),
                         refresh_token: None,
                         secret: None,
+                        ..Default::default()
                     },
                 ),
             ]
             .into_iter()
             .collect(),
+            ..Default::default()
         };
 
         let config_manager = ConfigManager::new();
@@ -87,6 +109,7 @@ mod tests {
                         client_id: "client_id_1".to_string(),
                         refresh_token: None,
                         secret: None,
+                        ..Default::default()
                     },
                 ),
                 (
@@ -96,11 +119,13 @@ mod tests {
                         client_id: "client_id_2".to_string(),
                         refresh_token: None,
                         secret: None,
+                        ..Default::default()
                     },
                 ),
             ]
             .into_iter()
             .collect(),
+            ..Default::default()
         };
 
         assert_eq!(config, target);
@@ -108,7 +133,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_logout_command_existing_client() {
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let mut config = ConfigFile {
             clients: {
@@ -120,14 +146,17 @@ mod tests {
                         client_id: "client123".to_string(),
                         refresh_token: Some("refresh123".to_string()),
                         secret: None,
+                        ..Default::default()
                     },
                 );
                 clients
             },
+            ..Default::default()
         };
 
         let logout_command = LogoutCommand {
             nickname: "test_client".to_string(),
+            local: true,
         };
 
         let mock_credentials_provider = MockCredentialsProvider;
@@ -146,14 +175,155 @@ mod tests {
         assert_eq!(client.refresh_token, None);
     }
 
+    fn revocable_config(auth_url: String) -> ConfigFile {
+        ConfigFile {
+            clients: [(
+                "test_client".to_string(),
+                AuthConfig {
+                    auth_url,
+                    client_id: "client123".to_string(),
+                    refresh_token: Some("refresh123".to_string()),
+                    secret: None,
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logout_command_revokes_at_server() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/protocol/openid-connect/revoke")
+                .body_contains("token=refresh123");
+            then.status(200);
+        });
+
+        let dir = tempdir().unwrap();
+
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let mut config = revocable_config(server.url(""));
+
+        let logout_command = LogoutCommand {
+            nickname: "test_client".to_string(),
+            local: false,
+        };
+
+        let mock_credentials_provider = MockCredentialsProvider;
+        let context = CommandContext {
+            config: &mut config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &mock_credentials_provider,
+        };
+
+        let result = logout_command.execute(context).await;
+        assert!(result.is_ok());
+        mock.assert();
+
+        let client = config.clients.get("test_client").unwrap();
+        assert_eq!(client.refresh_token, None);
+    }
+
+    #[tokio::test]
+    async fn test_logout_command_revokes_the_latest_refresh_token() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/protocol/openid-connect/revoke")
+                .body_contains("token=rotated");
+            then.status(200);
+        });
+
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let mut config = revocable_config(server.url(""));
+
+        // Another process rotated the refresh token after this one loaded the config.
+        let mut rotated = config.clone();
+        rotated
+            .clients
+            .get_mut("test_client")
+            .unwrap()
+            .refresh_token = Some("rotated".to_string());
+        config_manager
+            .save_config(&config_manager.get_config_path(), &rotated)
+            .unwrap();
+
+        let mock_credentials_provider = MockCredentialsProvider;
+        let context = CommandContext {
+            config: &mut config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &mock_credentials_provider,
+        };
+
+        let result = LogoutCommand {
+            nickname: "test_client".to_string(),
+            local: false,
+        }
+        .execute(context)
+        .await;
+        assert!(result.is_ok(), "{result:?}");
+        mock.assert();
+
+        let saved = config_manager
+            .read_config(&config_manager.get_config_path())
+            .unwrap()
+            .unwrap();
+        assert_eq!(saved.clients["test_client"].refresh_token, None);
+    }
+
+    #[tokio::test]
+    async fn test_logout_command_keeps_token_when_revocation_fails() {
+        let server = MockServer::start();
+        let _mock = server.mock(|when, then| {
+            when.method(POST).path("/protocol/openid-connect/revoke");
+            then.status(503);
+        });
+
+        let dir = tempdir().unwrap();
+
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let mut config = revocable_config(server.url(""));
+
+        let logout_command = LogoutCommand {
+            nickname: "test_client".to_string(),
+            local: false,
+        };
+
+        let mock_credentials_provider = MockCredentialsProvider;
+        let context = CommandContext {
+            config: &mut config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &mock_credentials_provider,
+        };
+
+        let result = logout_command.execute(context).await;
+        assert!(matches!(result, Err(TokensError::Network(_))));
+
+        let client = config.clients.get("test_client").unwrap();
+        assert_eq!(client.refresh_token, Some("refresh123".to_string()));
+    }
+
     #[tokio::test]
     async fn test_logout_command_nonexistent_client() {
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let mut config = ConfigFile::default();
 
         let logout_command = LogoutCommand {
             nickname: "nonexistent_client".to_string(),
+            local: false,
         };
 
         let mock_credentials_provider = MockCredentialsProvider;
@@ -165,6 +335,6 @@ mod tests {
         };
 
         let result = logout_command.execute(context).await;
-        assert!(result.is_ok());
+        assert!(matches!(result, Err(TokensError::ClientNotFound(_))));
     }
 }
@@ -1,19 +1,48 @@
 pub mod add;
+#[cfg(unix)]
+pub mod agent;
 pub mod delete;
+pub mod docker_credential;
+pub mod exec;
 pub mod get;
+pub mod git_credential;
+pub mod inspect;
+pub mod kube_credential;
 pub mod list;
 pub mod logout;
+pub mod proxy;
+pub mod request;
+pub mod template;
+pub mod vault;
 
-use crate::config::ConfigManager;
-use crate::oauth::TokenManager;
-use crate::types::{ConfigFile, CredentialsProvider};
+use crate::jwt::{self, Check, Jwt};
 use clap::{Parser, ValueEnum};
-use std::error::Error;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokens::ConfigManager;
+use tokens::TokenManager;
+use tokens::TokensError;
+use tokens::{ConfigFile, CredentialsProvider, HostMapping, unix_now};
+use tokio::time::Instant;
+
+/// How often long-running commands refresh when a token's expiry is unknown.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Parser, Clone, Debug, ValueEnum, PartialEq)]
 #[clap(rename_all = "lower")]
 pub enum Format {
+    /// `{"Authorization": "Bearer <token>"}`.
     Header,
+    /// `export TOKEN=<token>`, for `eval`.
+    Export,
+    /// `TOKEN=<token>`, for `.env` files.
+    Dotenv,
+    /// The token endpoint's response, including `expires_in`, `id_token` and `scope`.
+    Json,
+    /// `-H 'Authorization: Bearer <token>'`, for curl.
+    Curl,
+    /// A `.netrc` entry for the host given with `--machine`.
+    Netrc,
 }
 
 pub struct CommandContext<'a> {
@@ -23,7 +52,150 @@ pub struct CommandContext<'a> {
     pub credentials_provider: &'a dyn CredentialsProvider,
 }
 
+/// Fetch a token for a stored client with [`TokenManager::acquire`].
+pub async fn acquire_token(
+    context: &mut CommandContext<'_>,
+    nickname: &str,
+    fetch_refresh_token: bool,
+    scopes: &[String],
+) -> Result<String, TokensError> {
+    context
+        .token_manager
+        .acquire(
+            context.config_manager,
+            context.config,
+            nickname,
+            fetch_refresh_token,
+            scopes,
+            context.credentials_provider,
+        )
+        .await
+}
+
+/// Forget a cached access token that a resource server rejected, so the next
+/// [`acquire_token`] refreshes instead of reusing it. A token that another process
+/// has already replaced is left alone.
+pub fn invalidate_token(
+    context: &mut CommandContext<'_>,
+    nickname: &str,
+    token: &str,
+) -> Result<(), TokensError> {
+    let forget = |config: &mut ConfigFile| {
+        if let Some(client) = config.clients.get_mut(nickname)
+            && client
+                .access_token
+                .as_ref()
+                .is_some_and(|cached| cached.access_token == token)
+        {
+            client.access_token = None;
+        }
+    };
+
+    forget(context.config);
+    let config_path = context.config_manager.get_config_path();
+    context.config_manager.update_config(&config_path, forget)?;
+    Ok(())
+}
+
+/// When a token fetched by [`acquire_token`] expires, as a Unix timestamp: its
+/// cached expiry, or the JWT's `exp`.
+pub fn token_expiry(context: &CommandContext<'_>, nickname: &str, token: &str) -> Option<u64> {
+    context
+        .config
+        .clients
+        .get(nickname)
+        .and_then(|client| client.access_token.as_ref())
+        .filter(|cached| cached.access_token == token)
+        .map(|cached| cached.expires_at)
+        .or_else(|| {
+            Jwt::decode(token)
+                .ok()?
+                .timestamp("exp")
+                .and_then(|exp| u64::try_from(exp).ok())
+        })
+}
+
+/// When a token fetched by [`acquire_token`] stops being reusable: its expiry less
+/// the token manager's skew.
+pub fn refresh_deadline(context: &CommandContext<'_>, nickname: &str, token: &str) -> Instant {
+    let Some(expires_at) = token_expiry(context, nickname, token) else {
+        return Instant::now() + DEFAULT_REFRESH_INTERVAL;
+    };
+
+    let remaining = Duration::from_secs(expires_at.saturating_sub(unix_now()));
+    // Wait until just past the point the cached token is considered stale.
+    Instant::now()
+        + remaining.saturating_sub(context.token_manager.expiry_skew())
+        + Duration::from_secs(1)
+}
+
+/// One of the config's tables mapping hosts to the clients that serve them.
+pub type HostTable = fn(&mut ConfigFile) -> &mut HashMap<String, HostMapping>;
+
+/// Serve `host` from the client named in `mapping`, in memory and on disk.
+pub fn map_host(
+    context: &mut CommandContext<'_>,
+    table: HostTable,
+    host: &str,
+    mapping: HostMapping,
+) -> Result<(), TokensError> {
+    if !context.config.clients.contains_key(&mapping.nickname) {
+        return Err(TokensError::ClientNotFound(mapping.nickname));
+    }
+
+    table(context.config).insert(host.to_string(), mapping.clone());
+    let config_path = context.config_manager.get_config_path();
+    context
+        .config_manager
+        .update_config(&config_path, |config| {
+            table(config).insert(host.to_string(), mapping);
+        })?;
+    Ok(())
+}
+
+/// Remove the mapping for `host`, failing if there is none.
+pub fn unmap_host(
+    context: &mut CommandContext<'_>,
+    table: HostTable,
+    host: &str,
+) -> Result<(), TokensError> {
+    if table(context.config).remove(host).is_none() {
+        return Err(format!("No client is mapped to '{host}'.").into());
+    }
+
+    let config_path = context.config_manager.get_config_path();
+    context
+        .config_manager
+        .update_config(&config_path, |config| {
+            table(config).remove(host);
+        })?;
+    Ok(())
+}
+
+/// Check a token's signature and claims against the stored client's issuer.
+pub async fn verify_token(
+    context: &mut CommandContext<'_>,
+    nickname: &str,
+    token: &str,
+) -> Result<Vec<Check>, TokensError> {
+    let auth = context
+        .config_manager
+        .get_client_mut(context.config, nickname)
+        .ok_or_else(|| TokensError::ClientNotFound(nickname.to_string()))?;
+
+    let jwks = context.token_manager.fetch_jwks(auth).await?;
+    let issuer = context.token_manager.endpoints(auth).await.issuer;
+
+    Ok(jwt::verify(
+        token,
+        &jwks,
+        &issuer,
+        &auth.client_id,
+        unix_now() as i64,
+    ))
+}
+
 #[allow(async_fn_in_trait)]
 pub trait CommandHandler {
-    async fn execute(&self, context: CommandContext<'_>) -> Result<(), Box<dyn Error>>;
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError>;
 }
@@ -0,0 +1,457 @@
+use super::{CommandContext, CommandHandler, acquire_token, invalidate_token, refresh_deadline};
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::header::{AUTHORIZATION, HOST, HeaderMap, HeaderName, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokens::TokensError;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+/// How long to wait before retrying a failed background refresh.
+const REFRESH_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// How long to pause after a failed accept, e.g. when out of file descriptors.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Headers that describe a single connection and must not be forwarded (RFC 9110 section 7.6.1).
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Bodies are streamed through rather than buffered.
+type ProxyBody = UnsyncBoxBody<Bytes, reqwest::Error>;
+
+pub struct ProxyCommand {
+    pub nickname: String,
+    pub listen: SocketAddr,
+    /// Base URL that request paths are appended to.
+    pub upstream: String,
+    pub scopes: Vec<String>,
+}
+
+/// State shared with the connection tasks.
+struct Proxy {
+    client: reqwest::Client,
+    upstream: String,
+    token: RwLock<String>,
+    /// Tokens the upstream answered `401` to, for the refresh loop to replace.
+    rejected: mpsc::UnboundedSender<String>,
+}
+
+impl CommandHandler for ProxyCommand {
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError> {
+        let listener = TcpListener::bind(self.listen)
+            .await
+            .map_err(|e| format!("Failed to listen on {}: {e}", self.listen))?;
+
+        if !self.listen.ip().is_loopback() {
+            eprintln!(
+                "Warning: {} is reachable from other hosts, which can use the proxy with your token.",
+                self.listen
+            );
+        }
+        eprintln!(
+            "Proxying http://{} to {} as '{}'",
+            listener.local_addr()?,
+            self.upstream,
+            self.nickname
+        );
+
+        self.serve(context, listener).await
+    }
+}
+
+impl ProxyCommand {
+    /// Serve connections until interrupted, refreshing the token in the meantime.
+    async fn serve(
+        &self,
+        mut context: CommandContext<'_>,
+        listener: TcpListener,
+    ) -> Result<(), TokensError> {
+        let token = acquire_token(&mut context, &self.nickname, false, &self.scopes).await?;
+        let mut refresh_at = refresh_deadline(&context, &self.nickname, &token);
+
+        let (rejected, mut rejections) = mpsc::unbounded_channel();
+        let proxy = Arc::new(Proxy {
+            // Redirects are the client's to follow, with the client's own headers.
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .map_err(|e| format!("Failed to create HTTP client: {e}"))?,
+            upstream: self.upstream.trim_end_matches('/').to_string(),
+            token: RwLock::new(token),
+            rejected,
+        });
+
+        // Accept on a separate task so that a slow refresh never holds up new
+        // connections. Dropping the set stops it.
+        let mut accepting = JoinSet::new();
+        accepting.spawn(accept_connections(listener, proxy.clone()));
+
+        loop {
+            tokio::select! {
+                Some(token) = rejections.recv() => {
+                    if *proxy.token.read().unwrap() == token {
+                        if let Err(e) = invalidate_token(&mut context, &self.nickname, &token) {
+                            eprintln!("Failed to forget rejected token: {e}");
+                        }
+                        refresh_at = Instant::now();
+                    }
+                }
+                () = tokio::time::sleep_until(refresh_at) => {
+                    refresh_at = match acquire_token(&mut context, &self.nickname, false, &self.scopes).await {
+                        Ok(token) => {
+                            let deadline = refresh_deadline(&context, &self.nickname, &token);
+                            *proxy.token.write().unwrap() = token;
+                            deadline
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to refresh token, retrying in {REFRESH_RETRY_DELAY:?}: {e}");
+                            Instant::now() + REFRESH_RETRY_DELAY
+                        }
+                    };
+                }
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Accept connections for good, serving each on its own task.
+async fn accept_connections(listener: TcpListener, proxy: Arc<Proxy>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {e}");
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        let proxy = proxy.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| forward(req, proxy.clone()));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                eprintln!("Connection error: {e}");
+            }
+        });
+    }
+}
+
+async fn forward(
+    req: Request<Incoming>,
+    proxy: Arc<Proxy>,
+) -> Result<Response<ProxyBody>, Infallible> {
+    match try_forward(req, &proxy).await {
+        Ok(response) => Ok(response),
+        Err(e) => {
+            eprintln!("Upstream request failed: {e}");
+            let body = Full::new(Bytes::from(format!(
+                "tokens proxy: upstream request failed: {e}\n"
+            )));
+            let mut response = Response::new(body.map_err(|never| match never {}).boxed_unsync());
+            *response.status_mut() = StatusCode::BAD_GATEWAY;
+            Ok(response)
+        }
+    }
+}
+
+async fn try_forward(
+    req: Request<Incoming>,
+    proxy: &Proxy,
+) -> Result<Response<ProxyBody>, reqwest::Error> {
+    let (parts, body) = req.into_parts();
+    let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+    let url = format!("{}{path}", proxy.upstream);
+
+    let mut headers = forwardable(&parts.headers);
+    headers.remove(AUTHORIZATION);
+    // Not hop-by-hop, but it names the proxy; reqwest sets it from the upstream URL.
+    headers.remove(HOST);
+    let token = proxy.token.read().unwrap().clone();
+
+    let upstream = proxy
+        .client
+        .request(parts.method, url)
+        .headers(headers)
+        .bearer_auth(&token)
+        .body(reqwest::Body::wrap_stream(body.into_data_stream()))
+        .send()
+        .await?;
+
+    if upstream.status() == StatusCode::UNAUTHORIZED {
+        let _ = proxy.rejected.send(token);
+    }
+
+    let status = upstream.status();
+    let headers = forwardable(upstream.headers());
+    let body = StreamBody::new(upstream.bytes_stream().map_ok(Frame::data));
+
+    let mut response = Response::new(body.boxed_unsync());
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    Ok(response)
+}
+
+/// Copy end-to-end headers, dropping hop-by-hop ones and any the `Connection`
+/// header names.
+fn forwardable(headers: &HeaderMap) -> HeaderMap {
+    let connection: Vec<String> = headers
+        .get_all(hyper::header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            !HOP_BY_HOP_HEADERS.contains(&name) && !connection.iter().any(|c| c == name)
+        })
+        .map(|(name, value): (&HeaderName, &HeaderValue)| (name.clone(), value.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::Method::{GET, POST};
+    use httpmock::MockServer;
+    use std::future::Future;
+    use tempfile::tempdir;
+    use tokens::ConfigManager;
+    use tokens::TokenManager;
+    use tokens::{AuthConfig, CachedToken, ConfigFile, CredentialsProvider, unix_now};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
+    /// Run `client` against a proxy for the stored client `client`, stopping the
+    /// proxy once it completes.
+    async fn with_proxy<F, T>(
+        config: &mut ConfigFile,
+        upstream: String,
+        client: impl FnOnce(SocketAddr) -> F,
+    ) -> T
+    where
+        F: Future<Output = T>,
+    {
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let context = CommandContext {
+            config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &MockCredentialsProvider,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let command = ProxyCommand {
+            nickname: "client".to_string(),
+            listen: addr,
+            upstream,
+            scopes: vec![],
+        };
+
+        tokio::select! {
+            result = command.serve(context, listener) => panic!("proxy stopped: {result:?}"),
+            output = client(addr) => output,
+        }
+    }
+
+    #[tokio::test]
+    async fn proxy_injects_token_and_forwards_response() {
+        let upstream = MockServer::start();
+        let api = upstream.mock(|when, then| {
+            when.method(POST)
+                .path("/base/items")
+                .query_param("page", "2")
+                .header("authorization", "Bearer token123")
+                .header("x-trace", "abc")
+                .body("payload");
+            then.status(201).header("x-upstream", "yes").body("created");
+        });
+
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "client123".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "token123".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let response = with_proxy(&mut config, upstream.url("/base/"), |addr| async move {
+            reqwest::Client::new()
+                .post(format!("http://{addr}/items?page=2"))
+                .header("authorization", "Bearer ignored")
+                .header("x-trace", "abc")
+                .body("payload")
+                .send()
+                .await
+                .unwrap()
+        })
+        .await;
+
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.headers()["x-upstream"], "yes");
+        assert_eq!(response.text().await.unwrap(), "created");
+        api.assert();
+    }
+
+    #[tokio::test]
+    async fn proxy_passes_redirects_through() {
+        let upstream = MockServer::start();
+        let api = upstream.mock(|when, then| {
+            when.method(GET).path("/old");
+            then.status(302).header("location", "/new");
+        });
+
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "client123".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "token123".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let response = with_proxy(&mut config, upstream.url(""), |addr| async move {
+            reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap()
+                .get(format!("http://{addr}/old"))
+                .send()
+                .await
+                .unwrap()
+        })
+        .await;
+
+        assert_eq!(response.status(), 302);
+        assert_eq!(response.headers()["location"], "/new");
+        api.assert();
+    }
+
+    #[tokio::test]
+    async fn proxy_refreshes_token_rejected_by_upstream() {
+        let server = MockServer::start();
+        let rejected = server.mock(|when, then| {
+            when.method(GET)
+                .path("/items")
+                .header("authorization", "Bearer old");
+            then.status(401);
+        });
+        let accepted = server.mock(|when, then| {
+            when.method(GET)
+                .path("/items")
+                .header("authorization", "Bearer new");
+            then.status(200).body("ok");
+        });
+        let _refresh = server.mock(|when, then| {
+            when.method(POST)
+                .path("/protocol/openid-connect/token")
+                .body_contains("grant_type=refresh_token");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"access_token": "new", "expires_in": 300}"#);
+        });
+
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: server.url(""),
+                client_id: "client123".to_string(),
+                refresh_token: Some("refresh123".to_string()),
+                access_token: Some(CachedToken {
+                    access_token: "old".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let statuses = with_proxy(&mut config, server.url(""), |addr| async move {
+            let client = reqwest::Client::new();
+            let mut statuses = Vec::new();
+            for _ in 0..20 {
+                let response = client
+                    .get(format!("http://{addr}/items"))
+                    .send()
+                    .await
+                    .unwrap();
+                statuses.push(response.status().as_u16());
+                if response.status() == 200 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            statuses
+        })
+        .await;
+
+        assert_eq!(statuses.first(), Some(&401));
+        assert_eq!(statuses.last(), Some(&200));
+        rejected.assert_hits(statuses.len() - 1);
+        accepted.assert();
+    }
+
+    #[test]
+    fn hop_by_hop_headers_are_dropped() {
+        let mut headers = HeaderMap::new();
+        headers.insert("connection", HeaderValue::from_static("close, x-private"));
+        headers.insert("x-private", HeaderValue::from_static("1"));
+        headers.insert("transfer-encoding", HeaderValue::from_static("chunked"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+
+        let forwarded = forwardable(&headers);
+
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded["accept"], "*/*");
+    }
+}
@@ -0,0 +1,261 @@
+use super::{CommandContext, CommandHandler, acquire_token, invalidate_token};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Method, Response, StatusCode};
+use std::fs;
+use std::io::{self, Read, Write};
+use tokens::TokensError;
+
+pub struct RequestCommand {
+    pub nickname: String,
+    pub method: String,
+    pub url: String,
+    /// Extra headers as `Name: value`.
+    pub headers: Vec<String>,
+    /// File to send as the request body, or `-` for stdin.
+    pub body: Option<String>,
+    pub scopes: Vec<String>,
+    /// Print the response body exactly as received.
+    pub raw: bool,
+}
+
+impl CommandHandler for RequestCommand {
+    async fn execute(&self, mut context: CommandContext<'_>) -> Result<(), TokensError> {
+        let method = Method::from_bytes(self.method.to_uppercase().as_bytes())
+            .map_err(|_| format!("Invalid HTTP method '{}'.", self.method))?;
+        let headers = parse_headers(&self.headers)?;
+        let body = self.body.as_deref().map(read_body).transpose()?;
+
+        let client = Client::new();
+        let send = |token: String| {
+            let mut request = client
+                .request(method.clone(), &self.url)
+                .headers(headers.clone())
+                .bearer_auth(token);
+            if let Some(body) = &body {
+                if !headers.contains_key(CONTENT_TYPE)
+                    && serde_json::from_slice::<serde_json::Value>(body).is_ok()
+                {
+                    request = request.header(CONTENT_TYPE, "application/json");
+                }
+                request = request.body(body.clone());
+            }
+            request.send()
+        };
+
+        let token = acquire_token(&mut context, &self.nickname, false, &self.scopes).await?;
+        let mut response = send(token.clone()).await.map_err(request_error)?;
+
+        // The cached token may have been revoked early; refresh once and retry.
+        if response.status() == StatusCode::UNAUTHORIZED {
+            invalidate_token(&mut context, &self.nickname, &token)?;
+            let token = acquire_token(&mut context, &self.nickname, false, &self.scopes).await?;
+            response = send(token).await.map_err(request_error)?;
+        }
+
+        let status = response.status();
+        print_body(response, self.raw).await?;
+
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(format!("Request failed with status {status}.").into())
+        }
+    }
+}
+
+fn parse_headers(headers: &[String]) -> Result<HeaderMap, TokensError> {
+    let mut map = HeaderMap::new();
+    for header in headers {
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid header '{header}', expected 'Name: value'."))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|e| format!("Invalid header name '{name}': {e}"))?;
+        if name == AUTHORIZATION {
+            return Err("The Authorization header is set from the client's token.".into());
+        }
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|e| format!("Invalid value for header '{name}': {e}"))?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+fn read_body(path: &str) -> Result<Vec<u8>, TokensError> {
+    let body = if path == "-" {
+        let mut body = Vec::new();
+        io::stdin().read_to_end(&mut body)?;
+        body
+    } else {
+        fs::read(path).map_err(|e| format!("Failed to read body from {path}: {e}"))?
+    };
+    Ok(body)
+}
+
+/// Write the response body to stdout, pretty-printing JSON unless `raw` is set.
+async fn print_body(response: Response, raw: bool) -> Result<(), TokensError> {
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("json"));
+    let body = response.bytes().await.map_err(request_error)?;
+
+    let mut stdout = io::stdout().lock();
+    match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(json) if is_json && !raw => {
+            writeln!(stdout, "{}", serde_json::to_string_pretty(&json)?)?
+        }
+        _ => stdout.write_all(&body)?,
+    }
+    Ok(())
+}
+
+fn request_error(err: reqwest::Error) -> TokensError {
+    TokensError::Other(format!("Request failed: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::Method::{GET, POST};
+    use httpmock::MockServer;
+    use tempfile::tempdir;
+    use tokens::ConfigManager;
+    use tokens::TokenManager;
+    use tokens::{AuthConfig, CachedToken, ConfigFile, CredentialsProvider, unix_now};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
+    fn client_config(auth_url: String) -> ConfigFile {
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url,
+                client_id: "client123".to_string(),
+                refresh_token: Some("refresh123".to_string()),
+                access_token: Some(CachedToken {
+                    access_token: "old".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        config
+    }
+
+    async fn run(command: RequestCommand, config: &mut ConfigFile) -> Result<(), TokensError> {
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let context = CommandContext {
+            config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &MockCredentialsProvider,
+        };
+        command.execute(context).await
+    }
+
+    #[tokio::test]
+    async fn request_sends_token_headers_and_body() {
+        let server = MockServer::start();
+        let api = server.mock(|when, then| {
+            when.method(POST)
+                .path("/items")
+                .header("authorization", "Bearer old")
+                .header("x-trace", "abc")
+                .header("content-type", "application/json")
+                .body(r#"{"name": "item"}"#);
+            then.status(201)
+                .header("content-type", "application/json")
+                .body(r#"{"id": 1}"#);
+        });
+
+        let dir = tempdir().unwrap();
+        let body_path = dir.path().join("body.json");
+        fs::write(&body_path, r#"{"name": "item"}"#).unwrap();
+
+        let mut config = client_config(server.url(""));
+        let result = run(
+            RequestCommand {
+                nickname: "client".to_string(),
+                method: "post".to_string(),
+                url: server.url("/items"),
+                headers: vec!["X-Trace: abc".to_string()],
+                body: Some(body_path.to_string_lossy().into_owned()),
+                scopes: vec![],
+                raw: false,
+            },
+            &mut config,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{result:?}");
+        api.assert();
+    }
+
+    #[tokio::test]
+    async fn request_refreshes_once_on_unauthorized() {
+        let server = MockServer::start();
+        let rejected = server.mock(|when, then| {
+            when.method(GET)
+                .path("/items")
+                .header("authorization", "Bearer old");
+            then.status(401);
+        });
+        let accepted = server.mock(|when, then| {
+            when.method(GET)
+                .path("/items")
+                .header("authorization", "Bearer new");
+            then.status(200).body("ok");
+        });
+        let refresh = server.mock(|when, then| {
+            when.method(POST)
+                .path("/protocol/openid-connect/token")
+                .body_contains("grant_type=refresh_token");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    r#"{"access_token": "new", "refresh_token": "refresh456", "expires_in": 300}"#,
+                );
+        });
+
+        let mut config = client_config(server.url(""));
+        let result = run(
+            RequestCommand {
+                nickname: "client".to_string(),
+                method: "GET".to_string(),
+                url: server.url("/items"),
+                headers: vec![],
+                body: None,
+                scopes: vec![],
+                raw: false,
+            },
+            &mut config,
+        )
+        .await;
+
+        assert!(result.is_ok(), "{result:?}");
+        rejected.assert();
+        refresh.assert();
+        accepted.assert();
+        let cached = config.clients["client"].access_token.as_ref().unwrap();
+        assert_eq!(cached.access_token, "new");
+    }
+
+    #[test]
+    fn parse_headers_rejects_malformed_and_authorization() {
+        assert!(parse_headers(&["Accept: application/json".to_string()]).is_ok());
+        assert!(parse_headers(&["no-colon".to_string()]).is_err());
+        assert!(parse_headers(&["Authorization: Basic x".to_string()]).is_err());
+    }
+}
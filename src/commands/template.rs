@@ -0,0 +1,144 @@
+use super::{CommandContext, CommandHandler};
+use crate::template::{self, TemplateSource};
+use clap::Subcommand;
+use prettytable::{Table, row};
+use std::path::PathBuf;
+use tokens::TokensError;
+
+#[derive(Debug, Subcommand)]
+pub enum TemplateAction {
+    /// Store a template for use with `get --template-name`.
+    Add {
+        name: String,
+        #[arg(required_unless_present = "file")]
+        template: Option<String>,
+        /// Read the template from a file.
+        #[arg(long, value_name = "FILE", conflicts_with = "template")]
+        file: Option<PathBuf>,
+    },
+    /// Remove a stored template.
+    Remove { name: String },
+    /// List stored templates.
+    List,
+}
+
+pub struct TemplateCommand {
+    pub action: TemplateAction,
+}
+
+impl CommandHandler for TemplateCommand {
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError> {
+        let config_path = context.config_manager.get_config_path();
+
+        match &self.action {
+            TemplateAction::Add {
+                name,
+                template,
+                file,
+            } => {
+                let source = match (template, file) {
+                    (Some(template), _) => TemplateSource::Inline(template.clone()),
+                    (None, Some(path)) => TemplateSource::File(path.clone()),
+                    (None, None) => return Err("No template given.".into()),
+                };
+                let template = source.load(context.config)?;
+                // Catch malformed placeholders now rather than on first use.
+                template::render(&template, |_| Some(String::new()))?;
+
+                context
+                    .config
+                    .templates
+                    .insert(name.clone(), template.clone());
+                context
+                    .config_manager
+                    .update_config(&config_path, |config| {
+                        config.templates.insert(name.clone(), template);
+                    })?;
+                println!("Template '{name}' saved.");
+            }
+            TemplateAction::Remove { name } => {
+                if context.config.templates.remove(name).is_none() {
+                    return Err(format!("No template named '{name}'.").into());
+                }
+                context
+                    .config_manager
+                    .update_config(&config_path, |config| {
+                        config.templates.remove(name);
+                    })?;
+                println!("Template '{name}' removed.");
+            }
+            TemplateAction::List => {
+                let mut names: Vec<_> = context.config.templates.iter().collect();
+                names.sort();
+
+                let mut table = Table::new();
+                table.add_row(row!["Name", "Template"]);
+                for (name, template) in names {
+                    table.add_row(row![name, template.trim_end()]);
+                }
+                table.printstd();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use tokens::ConfigManager;
+    use tokens::TokenManager;
+    use tokens::{ConfigFile, CredentialsProvider};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn add_saves_template_and_rejects_malformed_ones() {
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
+        let token_manager = TokenManager::new();
+        let mut config = ConfigFile::default();
+
+        for (name, template, valid) in [
+            ("grpc", "authorization: Bearer {{access_token}}", true),
+            ("bad", "{{oops", false),
+            ("filter", "{{access_token | yaml}}", false),
+        ] {
+            let context = CommandContext {
+                config: &mut config,
+                config_manager: &config_manager,
+                token_manager: &token_manager,
+                credentials_provider: &MockCredentialsProvider,
+            };
+            let result = TemplateCommand {
+                action: TemplateAction::Add {
+                    name: name.to_string(),
+                    template: Some(template.to_string()),
+                    file: None,
+                },
+            }
+            .execute(context)
+            .await;
+            assert_eq!(result.is_ok(), valid, "{name}: {result:?}");
+        }
+
+        let saved = config_manager
+            .read_config(&config_manager.get_config_path())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            saved.templates.get("grpc").map(String::as_str),
+            Some("authorization: Bearer {{access_token}}")
+        );
+        assert!(!saved.templates.contains_key("bad"));
+        assert!(!saved.templates.contains_key("filter"));
+    }
+}
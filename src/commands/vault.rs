@@ -0,0 +1,117 @@
+use super::{CommandContext, CommandHandler};
+use clap::Subcommand;
+use std::env;
+use std::error::Error;
+use tokens::TokensError;
+use tokens::{VAULT_KEY_ENV, VAULT_PASSPHRASE_ENV};
+
+#[derive(Debug, Subcommand)]
+pub enum VaultAction {
+    /// Encrypt stored refresh tokens and secrets with a passphrase.
+    Init,
+    /// Print the derived key as a shell export, so later commands don't prompt.
+    Unlock,
+    /// Decrypt stored secrets and stop using a passphrase.
+    Disable,
+}
+
+pub struct VaultCommand {
+    pub action: VaultAction,
+}
+
+impl CommandHandler for VaultCommand {
+    async fn execute(&self, context: CommandContext<'_>) -> Result<(), TokensError> {
+        let config_path = context.config_manager.get_config_path();
+
+        match self.action {
+            VaultAction::Init => {
+                let passphrase = new_passphrase()?;
+                let lock = context.config_manager.lock(&config_path)?;
+                let mut config = lock.read()?.unwrap_or_default();
+                context
+                    .config_manager
+                    .enable_vault(&mut config, &passphrase)?;
+                context.config_manager.save_config(&config_path, &config)?;
+                *context.config = config;
+                println!("Vault enabled. Stored refresh tokens and secrets are now encrypted.");
+            }
+            VaultAction::Unlock => {
+                let header = context
+                    .config
+                    .vault
+                    .as_ref()
+                    .ok_or("The vault is not enabled.")?;
+                let key = context.config_manager.unlock(header)?;
+                println!("export {VAULT_KEY_ENV}={}", key.to_hex());
+            }
+            VaultAction::Disable => {
+                let lock = context.config_manager.lock(&config_path)?;
+                let mut config = lock.read()?.unwrap_or_default();
+                context.config_manager.disable_vault(&mut config)?;
+                context.config_manager.save_config(&config_path, &config)?;
+                *context.config = config;
+                println!("Vault disabled. Stored refresh tokens and secrets are now plaintext.");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn new_passphrase() -> Result<String, Box<dyn Error>> {
+    if let Ok(passphrase) = env::var(VAULT_PASSPHRASE_ENV) {
+        if passphrase.trim().is_empty() {
+            return Err(format!("{VAULT_PASSPHRASE_ENV} must not be empty.").into());
+        }
+        return Ok(passphrase);
+    }
+
+    let passphrase = rpassword::prompt_password("New vault passphrase: ")?;
+    if passphrase.trim().is_empty() {
+        return Err("The vault passphrase must not be empty.".into());
+    }
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err("Passphrases do not match.".into());
+    }
+
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{
+        CommandContext, CommandHandler,
+        vault::{VaultAction, VaultCommand},
+    };
+    use tokens::{ConfigFile, ConfigManager, CredentialsProvider, TokenManager};
+
+    struct MockCredentialsProvider;
+
+    impl CredentialsProvider for MockCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+            Ok(("user".into(), "pass".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vault_unlock_without_vault() {
+        let config_manager = ConfigManager::new();
+        let token_manager = TokenManager::new();
+        let mut config = ConfigFile::default();
+
+        let vault_command = VaultCommand {
+            action: VaultAction::Unlock,
+        };
+
+        let mock_credentials_provider = MockCredentialsProvider;
+        let context = CommandContext {
+            config: &mut config,
+            config_manager: &config_manager,
+            token_manager: &token_manager,
+            credentials_provider: &mock_credentials_provider,
+        };
+
+        let result = vault_command.execute(context).await;
+        assert!(result.is_err());
+    }
+}
@@ -1,40 +1,314 @@
 use crate::types::{AuthConfig, ConfigFile};
-use std::fs;
-use std::io;
+use crate::vault::{
+    VAULT_KEY_ENV, VAULT_PASSPHRASE_ENV, VaultHeader, VaultKey, decrypt_config, encrypt_config,
+    is_encrypted,
+};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 const CONFIG_DIR: &str = ".config/tokens";
 const CONFIG_FILE: &str = "config.json";
 
-#[derive(Default)]
-pub struct ConfigManager;
+/// The config holds refresh tokens and secrets, so only the owner may access it.
+const CONFIG_DIR_MODE: u32 = 0o700;
+const CONFIG_FILE_MODE: u32 = 0o600;
+
+/// Why the config file could not be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The vault is enabled but its key couldn't be obtained, so the stored secrets
+    /// can't be decrypted.
+    VaultLocked {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+impl ConfigError {
+    fn parse(path: &Path, err: serde_json::Error) -> Self {
+        let location = format!(" at line {} column {}", err.line(), err.column());
+        let message = err.to_string();
+        Self::Parse {
+            path: path.to_path_buf(),
+            line: err.line(),
+            column: err.column(),
+            message: message
+                .strip_suffix(&location)
+                .unwrap_or(&message)
+                .to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => {
+                write!(f, "Failed to read config {}: {source}", path.display())
+            }
+            Self::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Failed to parse config {}:{line}:{column}: {message}",
+                path.display()
+            ),
+            Self::VaultLocked { path, reason } => {
+                write!(f, "Cannot unlock the vault in {}: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Parse { .. } | Self::VaultLocked { .. } => None,
+        }
+    }
+}
+
+impl From<ConfigError> for io::Error {
+    fn from(err: ConfigError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct ConfigManager {
+    config_path: Option<PathBuf>,
+    vault_key: OnceLock<VaultKey>,
+}
+
+/// Exclusive advisory lock on the config, held until dropped. Other `tokens`
+/// processes block in [`ConfigManager::lock`] until it is released.
+pub struct ConfigLock<'a> {
+    manager: &'a ConfigManager,
+    path: PathBuf,
+    _file: fs::File,
+}
+
+impl ConfigLock<'_> {
+    /// Read the config as it is on disk now.
+    pub fn read(&self) -> Result<Option<ConfigFile>, ConfigError> {
+        self.manager.read_config(&self.path)
+    }
+
+    /// Re-read the config, apply `update` and write it back, so changes made by
+    /// other processes since this one loaded the config are kept.
+    pub fn update(&self, update: impl FnOnce(&mut ConfigFile)) -> io::Result<()> {
+        let mut config = self.read()?.unwrap_or_default();
+        update(&mut config);
+        self.manager.save_config(&self.path, &config)
+    }
+}
 
 impl ConfigManager {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Use a config file other than `~/.config/tokens/config.json`.
+    pub fn with_path(path: PathBuf) -> Self {
+        Self {
+            config_path: Some(path),
+            ..Self::default()
+        }
     }
 
     pub fn get_config_path(&self) -> PathBuf {
+        if let Some(path) = &self.config_path {
+            return path.clone();
+        }
         let home = dirs::home_dir().expect("Could not determine home directory.");
         home.join(CONFIG_DIR).join(CONFIG_FILE)
     }
 
-    pub fn read_config(&self, path: &Path) -> Option<ConfigFile> {
+    /// Take the cross-process lock guarding read-modify-write cycles on `path`.
+    pub fn lock(&self, path: &Path) -> io::Result<ConfigLock<'_>> {
+        let file = open_lock_file(path)?;
+        file.lock()?;
+
+        Ok(ConfigLock {
+            manager: self,
+            path: path.to_path_buf(),
+            _file: file,
+        })
+    }
+
+    /// Like [`ConfigManager::lock`], but waits for another process to release the
+    /// lock on a blocking thread instead of stalling the async runtime.
+    pub async fn lock_async(&self, path: &Path) -> io::Result<ConfigLock<'_>> {
+        let file = open_lock_file(path)?;
+        let file = tokio::task::spawn_blocking(move || file.lock().map(|()| file))
+            .await
+            .map_err(io::Error::other)??;
+
+        Ok(ConfigLock {
+            manager: self,
+            path: path.to_path_buf(),
+            _file: file,
+        })
+    }
+
+    /// Lock the config, re-read it, apply `update` and write it back.
+    pub fn update_config(
+        &self,
+        path: &Path,
+        update: impl FnOnce(&mut ConfigFile),
+    ) -> io::Result<()> {
+        self.lock(path)?.update(update)
+    }
+
+    /// Load the config, or `None` if it doesn't exist yet.
+    pub fn read_config(&self, path: &Path) -> Result<Option<ConfigFile>, ConfigError> {
         if path.exists() {
-            fs::read_to_string(path)
-                .ok()
-                .and_then(|data| serde_json::from_str(&data).ok())
+            warn_if_too_open(path, CONFIG_FILE_MODE);
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                warn_if_too_open(parent, CONFIG_DIR_MODE);
+            }
+
+            let data = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            let mut config: ConfigFile =
+                serde_json::from_str(&data).map_err(|e| ConfigError::parse(path, e))?;
+
+            // Never hand out the ciphertext, which would be sent as a refresh token
+            // or client secret.
+            if let Some(header) = config.vault.clone()
+                && let Err(e) = self
+                    .unlock(&header)
+                    .and_then(|key| decrypt_config(&mut config, key))
+            {
+                return Err(ConfigError::VaultLocked {
+                    path: path.to_path_buf(),
+                    reason: e.to_string(),
+                });
+            }
+
+            Ok(Some(config))
         } else {
-            None
+            Ok(None)
         }
     }
 
+    /// Write the config atomically: a private temp file in the same directory is
+    /// fsynced and renamed over the old file, so readers never see a partial write.
+    ///
+    /// The previous version is kept as `<file>.bak`, encrypted with the new vault
+    /// key if the vault was just enabled. A file that fails to parse is never
+    /// overwritten, since that would discard every client stored in it.
     pub fn save_config(&self, path: &Path, config: &ConfigFile) -> io::Result<()> {
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+        let dir = config_dir(path);
+        create_private_dir(dir)?;
+
+        if path.exists() {
+            let existing = fs::read_to_string(path)?;
+            let previous = serde_json::from_str::<ConfigFile>(&existing).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, ConfigError::parse(path, e))
+            })?;
+            let backup = match (&previous.vault, &config.vault) {
+                (None, Some(header)) => self.serialize(&ConfigFile {
+                    vault: Some(header.clone()),
+                    ..previous
+                })?,
+                _ => existing,
+            };
+            write_atomic(&backup_path(path), backup.as_bytes())?;
+        }
+
+        write_atomic(path, self.serialize(config)?.as_bytes())
+    }
+
+    /// Serialize `config`, encrypting its secrets if it has a vault.
+    fn serialize(&self, config: &ConfigFile) -> io::Result<String> {
+        let Some(header) = &config.vault else {
+            return Ok(serde_json::to_string_pretty(config)?);
+        };
+
+        let mut encrypted = config.clone();
+        self.unlock(header)
+            .and_then(|key| encrypt_config(&mut encrypted, key))
+            .map_err(|e| io::Error::other(format!("Cannot encrypt config: {e}")))?;
+        Ok(serde_json::to_string_pretty(&encrypted)?)
+    }
+
+    /// Derive the vault key from `TOKENS_VAULT_KEY`, `TOKENS_VAULT_PASSPHRASE` or a
+    /// passphrase prompt, caching it for the rest of the process.
+    pub fn unlock(&self, header: &VaultHeader) -> Result<&VaultKey, Box<dyn Error>> {
+        if let Some(key) = self.vault_key.get() {
+            return Ok(key);
+        }
+
+        let key = if let Ok(hex) = env::var(VAULT_KEY_ENV) {
+            VaultKey::from_hex(&hex, header)?
+        } else if let Ok(passphrase) = env::var(VAULT_PASSPHRASE_ENV) {
+            VaultKey::derive(&passphrase, header)?
+        } else {
+            let passphrase = rpassword::prompt_password("Vault passphrase: ")?;
+            VaultKey::derive(&passphrase, header)?
+        };
+
+        Ok(self.vault_key.get_or_init(|| key))
+    }
+
+    /// Start encrypting sensitive fields with a key derived from `passphrase`.
+    pub fn enable_vault(
+        &self,
+        config: &mut ConfigFile,
+        passphrase: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        if config.vault.is_some() {
+            return Err("The vault is already enabled.".into());
+        }
+
+        let (header, key) = VaultKey::create(passphrase)?;
+        self.vault_key
+            .set(key)
+            .map_err(|_| "A vault key is already loaded.")?;
+        config.vault = Some(header);
+        Ok(())
+    }
+
+    /// Stop encrypting sensitive fields. The config must already be unlocked.
+    pub fn disable_vault(&self, config: &mut ConfigFile) -> Result<(), Box<dyn Error>> {
+        let header = config.vault.clone().ok_or("The vault is not enabled.")?;
+        let key = self.unlock(&header)?;
+        decrypt_config(config, key)?;
+
+        let still_encrypted = config.clients.values().any(|client| {
+            [&client.refresh_token, &client.secret]
+                .into_iter()
+                .flatten()
+                .any(|value| is_encrypted(value))
+        });
+        if still_encrypted {
+            return Err("Some stored secrets could not be decrypted.".into());
         }
-        let data = serde_json::to_string_pretty(config)?;
-        fs::write(path, data)
+
+        config.vault = None;
+        Ok(())
     }
 
     pub fn add_client(&self, config: &mut ConfigFile, nickname: String, auth_config: AuthConfig) {
@@ -53,7 +327,6 @@ impl ConfigManager {
         config.clients.get_mut(nickname)
     }
 
-    #[allow(dead_code)]
     pub fn get_client<'a>(&self, config: &'a ConfigFile, nickname: &str) -> Option<&'a AuthConfig> {
         config.clients.get(nickname)
     }
@@ -62,3 +335,369 @@ impl ConfigManager {
         config.clients.iter().collect()
     }
 }
+
+/// Open (creating if needed) the lock file next to `path`, without locking it.
+fn open_lock_file(path: &Path) -> io::Result<fs::File> {
+    let dir = config_dir(path);
+    create_private_dir(dir)?;
+
+    let mut lock_name = path.file_name().unwrap_or_default().to_os_string();
+    lock_name.push(".lock");
+
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, CONFIG_FILE_MODE);
+
+    options.open(dir.join(lock_name))
+}
+
+fn config_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Replace `path` with `data` via a synced temp file in the same directory.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let dir = config_dir(path);
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("Config path has no file name."))?
+        .to_string_lossy();
+    let tmp_path = dir.join(format!(
+        ".{file_name}.{}.{:08x}.tmp",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+
+    let result = write_synced(&tmp_path, data)
+        .and_then(|()| fs::rename(&tmp_path, path))
+        .and_then(|()| sync_dir(dir));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+pub(crate) fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, CONFIG_DIR_MODE);
+    builder.create(dir)
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, CONFIG_FILE_MODE);
+
+    let mut file = options.open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Persist the rename itself by syncing the containing directory.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// Permission bits on `path` beyond `allowed`, if any.
+#[cfg(unix)]
+fn excess_permissions(path: &Path, allowed: u32) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    (mode & !allowed != 0).then_some(mode)
+}
+
+fn warn_if_too_open(path: &Path, allowed: u32) {
+    #[cfg(unix)]
+    if let Some(mode) = excess_permissions(path, allowed) {
+        eprintln!(
+            "Warning: {} has permissions {mode:o}, which allows other users to read it. Run `chmod {allowed:o} {}`.",
+            path.display(),
+            path.display()
+        );
+    }
+    #[cfg(not(unix))]
+    let _ = (path, allowed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_config_is_private_and_atomic() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path().join("tokens");
+        let path = config_dir.join("config.json");
+
+        let config_manager = ConfigManager::new();
+        let mut config = ConfigFile::default();
+        config_manager.save_config(&path, &config).unwrap();
+
+        config.expiry_skew = Some(10);
+        config_manager.save_config(&path, &config).unwrap();
+
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(&config_dir), 0o700);
+        assert_eq!(mode(&config_dir.join("config.json.bak")), 0o600);
+        let mut entries: Vec<_> = fs::read_dir(&config_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, vec!["config.json", "config.json.bak"]);
+        assert_eq!(config_manager.read_config(&path).unwrap(), Some(config));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_excess_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, "{}").unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(excess_permissions(&path, CONFIG_FILE_MODE), Some(0o644));
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(excess_permissions(&path, CONFIG_FILE_MODE), None);
+    }
+
+    #[test]
+    fn test_update_config_keeps_concurrent_changes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let config_manager = ConfigManager::new();
+                    config_manager
+                        .update_config(&path, |config| {
+                            config.clients.insert(
+                                format!("client{i}"),
+                                AuthConfig {
+                                    client_id: format!("id{i}"),
+                                    ..Default::default()
+                                },
+                            );
+                        })
+                        .unwrap();
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        let config = ConfigManager::new().read_config(&path).unwrap().unwrap();
+        assert_eq!(config.clients.len(), 8);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_lock_async_waits_without_blocking_runtime() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let config_manager = ConfigManager::new();
+
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let holder = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let _held = ConfigManager::new().lock(&path).unwrap();
+                locked_tx.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(200));
+            })
+        };
+        locked_rx.recv().unwrap();
+
+        let (acquired_at, ticked_at) = tokio::join!(
+            async {
+                let _lock = config_manager.lock_async(&path).await.unwrap();
+                std::time::Instant::now()
+            },
+            async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                std::time::Instant::now()
+            },
+        );
+        holder.join().unwrap();
+
+        assert!(ticked_at < acquired_at);
+    }
+
+    #[test]
+    fn test_read_config_missing_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        assert!(ConfigManager::new().read_config(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_config_reports_parse_location() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, "{\n  \"clients\": {,}\n}").unwrap();
+
+        let err = ConfigManager::new().read_config(&path).unwrap_err();
+
+        match &err {
+            ConfigError::Parse { line, column, .. } => assert_eq!((*line, *column), (2, 15)),
+            other => panic!("unexpected error: {other}"),
+        }
+        assert!(err.to_string().contains("config.json:2:15"));
+    }
+
+    #[test]
+    fn test_save_config_refuses_to_overwrite_corrupt_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        let config_manager = ConfigManager::new();
+        let result = config_manager.save_config(&path, &ConfigFile::default());
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ not json");
+        assert!(
+            config_manager
+                .update_config(&path, |config| config.expiry_skew = Some(1))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_with_path_overrides_config_path() {
+        let config_manager = ConfigManager::with_path(PathBuf::from("/tmp/tokens.json"));
+        assert_eq!(
+            config_manager.get_config_path(),
+            PathBuf::from("/tmp/tokens.json")
+        );
+    }
+
+    #[test]
+    fn test_vault_encrypts_on_save_and_decrypts_on_read() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: "https://example.com".to_string(),
+                client_id: "client123".to_string(),
+                refresh_token: Some("refresh123".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let config_manager = ConfigManager::new();
+        config_manager
+            .enable_vault(&mut config, "correct horse")
+            .unwrap();
+        config_manager.save_config(&path, &config).unwrap();
+
+        let on_disk = fs::read_to_string(&path).unwrap();
+        assert!(!on_disk.contains("refresh123"));
+        assert!(on_disk.contains("client123"));
+
+        let read_back = config_manager.read_config(&path).unwrap().unwrap();
+        assert_eq!(read_back, config);
+    }
+
+    #[test]
+    fn test_vault_init_leaves_no_plaintext_backup() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                client_id: "client123".to_string(),
+                refresh_token: Some("refresh123".to_string()),
+                secret: Some("secret123".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let config_manager = ConfigManager::new();
+        config_manager.save_config(&path, &config).unwrap();
+        config_manager
+            .enable_vault(&mut config, "correct horse")
+            .unwrap();
+        config_manager.save_config(&path, &config).unwrap();
+
+        let backup = fs::read_to_string(dir.path().join("config.json.bak")).unwrap();
+        assert!(!backup.contains("refresh123"));
+        assert!(!backup.contains("secret123"));
+
+        // The backup can still be restored with the vault key.
+        let restored = config_manager
+            .read_config(&dir.path().join("config.json.bak"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(restored, config);
+    }
+
+    #[test]
+    fn test_read_config_fails_when_vault_cannot_be_unlocked() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                refresh_token: Some("refresh123".to_string()),
+                ..Default::default()
+            },
+        );
+        let config_manager = ConfigManager::new();
+        config_manager
+            .enable_vault(&mut config, "correct horse")
+            .unwrap();
+        config_manager.save_config(&path, &config).unwrap();
+
+        // A manager holding the key of another vault can't decrypt this one.
+        let other = ConfigManager::new();
+        other
+            .enable_vault(&mut ConfigFile::default(), "battery staple")
+            .unwrap();
+
+        let result = other.read_config(&path);
+        assert!(
+            matches!(result, Err(ConfigError::VaultLocked { .. })),
+            "{result:?}"
+        );
+        assert_eq!(
+            crate::TokensError::from(result.unwrap_err()).exit_code(),
+            10
+        );
+    }
+}
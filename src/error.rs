@@ -0,0 +1,242 @@
+use crate::config::ConfigError;
+use crate::oauth::OAuthError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Failures a command can end with, each mapped to its own process exit code so
+/// scripts can tell them apart.
+#[derive(Debug)]
+pub enum TokensError {
+    /// No stored client has the given nickname.
+    ClientNotFound(String),
+    /// The authorization server rejected the refresh token or credentials.
+    InvalidGrant(String),
+    /// The authorization server could not be reached or is unavailable.
+    Network(String),
+    /// The authorization server answered with an OAuth error response.
+    OAuth(OAuthError),
+    /// The config file exists but cannot be parsed.
+    ConfigCorrupt(String),
+    /// The user cancelled an interactive prompt.
+    UserAborted,
+    /// A login is needed but prompting is disabled or there is no terminal.
+    LoginRequired,
+    /// The config's vault could not be unlocked, so its secrets are unavailable.
+    VaultLocked(String),
+    /// An error reported by `tokens agent`, with the exit code it maps to.
+    Agent { message: String, exit_code: i32 },
+    /// A command run by `tokens exec` exited unsuccessfully with this code.
+    ChildExited(i32),
+    /// Anything else.
+    Other(String),
+}
+
+impl TokensError {
+    /// Exit code for the process. `2` is left to clap for usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Other(_) => 1,
+            Self::ClientNotFound(_) => 3,
+            Self::InvalidGrant(_) => 4,
+            Self::Network(_) => 5,
+            Self::ConfigCorrupt(_) => 6,
+            Self::UserAborted => 7,
+            Self::LoginRequired => 9,
+            Self::VaultLocked(_) => 10,
+            Self::Agent { exit_code, .. } => *exit_code,
+            Self::ChildExited(code) => *code,
+            Self::OAuth(err) => match err.error.as_str() {
+                "invalid_grant" => 4,
+                "server_error" | "temporarily_unavailable" => 5,
+                _ => 8,
+            },
+        }
+    }
+
+    /// Prefix the message of an unclassified error with what was being attempted.
+    pub fn context(self, action: &str) -> Self {
+        match self {
+            Self::Other(msg) => Self::Other(format!("{action}: {msg}")),
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for TokensError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ClientNotFound(nickname) => write!(f, "Client '{nickname}' not found."),
+            Self::InvalidGrant(msg) => {
+                write!(f, "The authorization server rejected the request: {msg}")
+            }
+            Self::Network(msg) => write!(f, "Could not reach the authorization server: {msg}"),
+            Self::OAuth(err) => write!(f, "The authorization server rejected the request: {err}"),
+            Self::ConfigCorrupt(msg) => write!(
+                f,
+                "{msg}\nFix the file or restore it from the .bak copy next to it."
+            ),
+            Self::UserAborted => write!(f, "Aborted."),
+            Self::LoginRequired => write!(
+                f,
+                "No usable refresh token and interactive login is disabled. Log in again from a terminal."
+            ),
+            Self::VaultLocked(msg) => write!(
+                f,
+                "{msg}\nEnter the passphrase at a terminal, or set TOKENS_VAULT_KEY or TOKENS_VAULT_PASSPHRASE."
+            ),
+            Self::Agent { message, .. } => write!(f, "{message}"),
+            Self::ChildExited(code) => write!(f, "Command exited with status {code}."),
+            Self::Other(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl Error for TokensError {}
+
+impl From<Box<dyn Error>> for TokensError {
+    fn from(err: Box<dyn Error>) -> Self {
+        match err.downcast::<TokensError>() {
+            Ok(err) => *err,
+            Err(err) => classify(err.as_ref()).unwrap_or_else(|| Self::Other(err.to_string())),
+        }
+    }
+}
+
+impl From<io::Error> for TokensError {
+    fn from(err: io::Error) -> Self {
+        classify(&err).unwrap_or_else(|| Self::Other(err.to_string()))
+    }
+}
+
+impl From<ConfigError> for TokensError {
+    fn from(err: ConfigError) -> Self {
+        classify(&err).unwrap_or_else(|| Self::Other(err.to_string()))
+    }
+}
+
+impl From<serde_json::Error> for TokensError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Other(err.to_string())
+    }
+}
+
+impl From<String> for TokensError {
+    fn from(msg: String) -> Self {
+        Self::Other(msg)
+    }
+}
+
+impl From<&str> for TokensError {
+    fn from(msg: &str) -> Self {
+        Self::Other(msg.to_string())
+    }
+}
+
+fn classify(err: &(dyn Error + 'static)) -> Option<TokensError> {
+    if let Some(err) = err.downcast_ref::<OAuthError>() {
+        return Some(TokensError::OAuth(err.clone()));
+    }
+
+    if let Some(err) = err.downcast_ref::<ConfigError>() {
+        return match err {
+            ConfigError::Parse { .. } => Some(TokensError::ConfigCorrupt(err.to_string())),
+            ConfigError::VaultLocked { .. } => Some(TokensError::VaultLocked(err.to_string())),
+            ConfigError::Io { .. } => None,
+        };
+    }
+
+    if let Some(err) = err.downcast_ref::<io::Error>() {
+        if let Some(classified) = err.get_ref().and_then(|inner| classify(inner)) {
+            return Some(classified);
+        }
+        return match err.kind() {
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::Interrupted => {
+                Some(TokensError::UserAborted)
+            }
+            _ => None,
+        };
+    }
+
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return match err.status().map(|s| s.as_u16()) {
+            Some(400 | 401) => Some(TokensError::InvalidGrant(err.to_string())),
+            Some(500..) | None if !err.is_decode() && !err.is_builder() => {
+                Some(TokensError::Network(err.to_string()))
+            }
+            _ => None,
+        };
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let errors = [
+            TokensError::Other(String::new()),
+            TokensError::ClientNotFound(String::new()),
+            TokensError::InvalidGrant(String::new()),
+            TokensError::Network(String::new()),
+            TokensError::ConfigCorrupt(String::new()),
+            TokensError::UserAborted,
+            TokensError::LoginRequired,
+            TokensError::VaultLocked(String::new()),
+        ];
+
+        let mut codes: Vec<_> = errors.iter().map(TokensError::exit_code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0) && !codes.contains(&2));
+    }
+
+    #[test]
+    fn boxed_errors_are_classified() {
+        let aborted: Box<dyn Error> = Box::new(io::Error::from(io::ErrorKind::UnexpectedEof));
+        assert!(matches!(
+            TokensError::from(aborted),
+            TokensError::UserAborted
+        ));
+
+        let corrupt = io::Error::new(
+            io::ErrorKind::InvalidData,
+            ConfigError::Parse {
+                path: PathBuf::from("config.json"),
+                line: 1,
+                column: 2,
+                message: "expected value".to_string(),
+            },
+        );
+        assert!(matches!(
+            TokensError::from(corrupt),
+            TokensError::ConfigCorrupt(_)
+        ));
+
+        let typed: Box<dyn Error> = Box::new(TokensError::ClientNotFound("a".to_string()));
+        assert!(matches!(
+            TokensError::from(typed),
+            TokensError::ClientNotFound(_)
+        ));
+
+        let rejected: Box<dyn Error> = Box::new(OAuthError {
+            error: "unauthorized_client".to_string(),
+            error_description: Some("Client not allowed for direct access grants".to_string()),
+            error_uri: None,
+        });
+        let rejected = TokensError::from(rejected);
+        assert_eq!(rejected.exit_code(), 8);
+        assert_eq!(
+            rejected.to_string(),
+            "The authorization server rejected the request: unauthorized_client: Client not allowed for direct access grants"
+        );
+
+        let other: Box<dyn Error> = "boom".into();
+        assert_eq!(TokensError::from(other).exit_code(), 1);
+    }
+}
@@ -0,0 +1,201 @@
+//! Manage OAuth clients and fetch their tokens, sharing the config and refresh
+//! tokens stored by the `tokens` CLI.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), tokens::TokensError> {
+//! let token = tokens::Tokens::new().token("my-client").await?;
+//! println!("Authorization: Bearer {token}");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Services that must never prompt can disable interactive logins, or supply
+//! their own [`CredentialsProvider`] for the password grant:
+//!
+//! ```no_run
+//! use tokens::{CredentialsProvider, TokenManager, Tokens};
+//!
+//! struct ServiceAccount;
+//!
+//! impl CredentialsProvider for ServiceAccount {
+//!     fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
+//!         Ok(("svc".to_string(), std::env::var("SVC_PASSWORD")?))
+//!     }
+//! }
+//!
+//! let tokens = Tokens::new()
+//!     .with_credentials_provider(ServiceAccount)
+//!     .with_token_manager(TokenManager::new().with_interactive(false));
+//! ```
+
+#[cfg(unix)]
+pub mod agent;
+mod config;
+mod error;
+mod oauth;
+mod types;
+mod vault;
+
+pub use config::{ConfigError, ConfigLock, ConfigManager};
+pub use error::TokensError;
+pub use oauth::{DEFAULT_EXPIRY_SKEW, OAuthError, TokenManager};
+pub use types::{
+    AuthConfig, CachedToken, ConfigFile, ConsoleCredentialsProvider, CredentialsProvider,
+    DEFAULT_HOST_USERNAME, GrantType, HostMapping, unix_now,
+};
+pub use vault::{VAULT_KEY_ENV, VAULT_PASSPHRASE_ENV};
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Fetches tokens for clients stored in the user's config, refreshing and
+/// persisting them exactly as `tokens get` does.
+pub struct Tokens {
+    config_manager: ConfigManager,
+    token_manager: Option<TokenManager>,
+    credentials_provider: Box<dyn CredentialsProvider>,
+}
+
+impl Default for Tokens {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tokens {
+    /// Use `~/.config/tokens/config.json` and prompt on the console when a login is needed.
+    pub fn new() -> Self {
+        Self {
+            config_manager: ConfigManager::new(),
+            token_manager: None,
+            credentials_provider: Box::new(ConsoleCredentialsProvider),
+        }
+    }
+
+    pub fn with_config_path(mut self, path: PathBuf) -> Self {
+        self.config_manager = ConfigManager::with_path(path);
+        self
+    }
+
+    pub fn with_credentials_provider(
+        mut self,
+        credentials_provider: impl CredentialsProvider + 'static,
+    ) -> Self {
+        self.credentials_provider = Box::new(credentials_provider);
+        self
+    }
+
+    /// Replace the default token manager, which uses the config's `expiry_skew`.
+    pub fn with_token_manager(mut self, token_manager: TokenManager) -> Self {
+        self.token_manager = Some(token_manager);
+        self
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.config_manager.get_config_path()
+    }
+
+    /// Load the config, or an empty one if none has been written yet.
+    pub fn load_config(&self) -> Result<ConfigFile, TokensError> {
+        Ok(self
+            .config_manager
+            .read_config(&self.config_path())?
+            .unwrap_or_default())
+    }
+
+    /// Fetch an access token for the client stored as `nickname`.
+    pub async fn token(&self, nickname: &str) -> Result<String, TokensError> {
+        self.token_with_scopes(nickname, &[]).await
+    }
+
+    /// Fetch an access token with additional scopes. Scoped tokens are not cached.
+    pub async fn token_with_scopes(
+        &self,
+        nickname: &str,
+        scopes: &[String],
+    ) -> Result<String, TokensError> {
+        self.acquire(nickname, false, scopes).await
+    }
+
+    /// Fetch the client's refresh token, logging in first if none is usable.
+    pub async fn refresh_token(&self, nickname: &str) -> Result<String, TokensError> {
+        self.acquire(nickname, true, &[]).await
+    }
+
+    async fn acquire(
+        &self,
+        nickname: &str,
+        fetch_refresh_token: bool,
+        scopes: &[String],
+    ) -> Result<String, TokensError> {
+        let mut config = self.load_config()?;
+        let default_manager;
+        let token_manager = match &self.token_manager {
+            Some(token_manager) => token_manager,
+            None => {
+                let skew = config
+                    .expiry_skew
+                    .map_or(DEFAULT_EXPIRY_SKEW, Duration::from_secs);
+                default_manager = TokenManager::new().with_expiry_skew(skew);
+                &default_manager
+            }
+        };
+
+        token_manager
+            .acquire(
+                &self.config_manager,
+                &mut config,
+                nickname,
+                fetch_refresh_token,
+                scopes,
+                self.credentials_provider.as_ref(),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::CachedToken;
+    use crate::types::unix_now;
+    use tempfile::tempdir;
+
+    #[test]
+    fn token_future_is_send() {
+        fn assert_send<T: Send>(_: T) {}
+
+        // Services spawn token fetches onto multi-threaded runtimes.
+        let tokens = Tokens::new();
+        assert_send(tokens.token("client"));
+    }
+
+    #[tokio::test]
+    async fn token_reads_the_stored_client() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: "http://127.0.0.1:1".to_string(),
+                client_id: "client123".to_string(),
+                access_token: Some(CachedToken {
+                    access_token: "cached".to_string(),
+                    expires_at: unix_now() + 3600,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        ConfigManager::new().save_config(&path, &config).unwrap();
+
+        let tokens = Tokens::new().with_config_path(path);
+
+        assert_eq!(tokens.token("client").await.unwrap(), "cached");
+        assert!(matches!(
+            tokens.token("missing").await,
+            Err(TokensError::ClientNotFound(_))
+        ));
+    }
+}
@@ -1,22 +1,49 @@
 mod commands;
-mod config;
-mod oauth;
-mod types;
+mod jwt;
+mod template;
 
 use clap::{Parser, Subcommand};
+#[cfg(unix)]
+use commands::agent::AgentCommand;
 use commands::{
-    CommandContext, CommandHandler, Format, add::AddCommand, delete::DeleteCommand,
-    get::GetCommand, list::ListCommand, logout::LogoutCommand,
+    CommandContext, CommandHandler, Format,
+    add::AddCommand,
+    delete::DeleteCommand,
+    docker_credential::{DOCKER_HELPER_NAME, DockerCredentialAction, DockerCredentialCommand},
+    exec::ExecCommand,
+    get::GetCommand,
+    git_credential::{GitCredentialAction, GitCredentialCommand},
+    inspect::InspectCommand,
+    kube_credential::KubeCredentialCommand,
+    list::ListCommand,
+    logout::LogoutCommand,
+    proxy::ProxyCommand,
+    request::RequestCommand,
+    template::{TemplateAction, TemplateCommand},
+    vault::{VaultAction, VaultCommand},
+};
+use std::env;
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use template::TemplateSource;
+#[cfg(unix)]
+use tokens::agent::AGENT_SOCK_ENV;
+use tokens::{
+    ConfigFile, ConfigManager, ConsoleCredentialsProvider, CredentialsProvider,
+    DEFAULT_EXPIRY_SKEW, GrantType, TokenManager, TokensError,
 };
-use config::ConfigManager;
-use oauth::TokenManager;
-use types::{ConfigFile, ConsoleCredentialsProvider};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Manage OAuth2 clients and tokens")]
 struct Args {
     #[command(subcommand)]
     cmd: Command,
+    /// Fail instead of logging in when no usable token is stored. Password prompts
+    /// also fail when stdin is not a terminal.
+    #[arg(long, global = true)]
+    no_interactive: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -29,11 +56,42 @@ enum Command {
         #[arg(short, long)]
         refresh_token: bool,
         /// Special output formats.
-        #[arg(short, long)]
+        #[arg(short, long, group = "output")]
         format: Option<Format>,
         /// Additional scopes. Expects a space-delimitered list.
         #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
         scopes: Vec<String>,
+        /// Verify the token's signature and claims against the issuer, reporting to stderr.
+        #[arg(long, conflicts_with = "refresh_token")]
+        verify: bool,
+        /// Variable name for the `export` and `dotenv` formats.
+        #[arg(short, long, default_value = "TOKEN", value_name = "NAME", value_parser = env_var_name)]
+        env_var: String,
+        /// Host to write the entry for with the `netrc` format.
+        #[arg(long, value_name = "HOST", required_if_eq("format", "netrc"), value_parser = netrc_machine)]
+        machine: Option<String>,
+        /// Render the output from a template, e.g. 'authorization: Bearer {{access_token}}'.
+        /// Placeholders: access_token, refresh_token, expires_at, nickname, client_id and claims.<path>.
+        /// Write '{{name | json}}' to insert a value as a JSON string.
+        #[arg(short, long, value_name = "TEMPLATE", group = "output")]
+        template: Option<String>,
+        /// Read the template from a file.
+        #[arg(long, value_name = "FILE", group = "output")]
+        template_file: Option<PathBuf>,
+        /// Use a template stored with `tokens template add`.
+        #[arg(long, value_name = "NAME", group = "output")]
+        template_name: Option<String>,
+    },
+    /// Decode and display the header and claims of an access token.
+    Inspect {
+        /// Client nickname. Reads a raw token from stdin when omitted.
+        nickname: Option<String>,
+        /// Additional scopes. Expects a space-delimitered list.
+        #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+        scopes: Vec<String>,
+        /// Verify the token's signature and claims against the client's issuer.
+        #[arg(long, requires = "nickname")]
+        verify: bool,
     },
     /// List stored clients.
     List,
@@ -47,20 +105,137 @@ enum Command {
         client_id: String,
         #[arg(short, long)]
         secret: Option<String>,
+        /// How to log in when no refresh token is stored.
+        #[arg(short, long, value_enum, default_value_t = GrantType::Password)]
+        grant_type: GrantType,
     },
     /// Remove a saved client.
     Delete { nickname: String },
     /// Logout of client.
-    Logout { nickname: String },
+    Logout {
+        nickname: String,
+        /// Only forget the refresh token locally, without revoking it at the server.
+        #[arg(short, long)]
+        local: bool,
+    },
+    /// Run a command with an access token in its environment.
+    Exec {
+        nickname: String,
+        /// Environment variable to export the access token as.
+        #[arg(short, long, default_value = "TOKEN")]
+        env_var: String,
+        /// Also export the refresh token under this name.
+        #[arg(long, value_name = "NAME")]
+        refresh_token_var: Option<String>,
+        /// Also export an `Authorization` header value (`Bearer <token>`) under this name.
+        #[arg(long, value_name = "NAME")]
+        header_var: Option<String>,
+        /// Additional scopes. Expects a space-delimitered list.
+        #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+        scopes: Vec<String>,
+        /// Command to run, after `--`.
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Send an HTTP request with the client's access token attached.
+    Request {
+        nickname: String,
+        /// HTTP method, e.g. GET or POST.
+        method: String,
+        url: String,
+        /// Extra request header as `Name: value`. Can be repeated.
+        #[arg(short = 'H', long = "header", value_name = "HEADER")]
+        headers: Vec<String>,
+        /// File to send as the request body, or `-` to read it from stdin.
+        #[arg(short, long, value_name = "FILE")]
+        body: Option<String>,
+        /// Additional scopes. Expects a space-delimitered list.
+        #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+        scopes: Vec<String>,
+        /// Print the response body as received, without pretty-printing JSON.
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Run a local reverse proxy that attaches the client's access token to each request.
+    Proxy {
+        nickname: String,
+        /// Address to accept connections on.
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+        /// Base URL to forward requests to.
+        #[arg(short, long)]
+        upstream: String,
+        /// Additional scopes. Expects a space-delimitered list.
+        #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+        scopes: Vec<String>,
+    },
+    /// Keep tokens in memory and serve them over a Unix socket, refreshing them
+    /// before they expire. Other commands use it when TOKENS_AGENT_SOCK is set.
+    #[cfg(unix)]
+    Agent {
+        /// Socket to listen on. Defaults to `$XDG_RUNTIME_DIR/tokens/agent.sock`.
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+    /// Print an ExecCredential for kubectl's `client.authentication.k8s.io/v1` exec plugin.
+    KubeCredential {
+        nickname: String,
+        /// Additional scopes. Expects a space-delimitered list.
+        #[arg(short, long, num_args = 1.., value_delimiter = ' ')]
+        scopes: Vec<String>,
+    },
+    /// Act as a git credential helper, answering with access tokens for mapped hosts.
+    GitCredential {
+        #[command(subcommand)]
+        action: GitCredentialAction,
+    },
+    /// Act as a docker credential helper, answering with access tokens for mapped registries.
+    /// Also used when the binary is run as `docker-credential-tokens`.
+    DockerCredential {
+        #[command(subcommand)]
+        action: DockerCredentialAction,
+    },
+    /// Manage output templates stored for `get --template-name`.
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
+    /// Manage encryption of stored refresh tokens and secrets.
+    Vault {
+        #[command(subcommand)]
+        action: VaultAction,
+    },
 }
 
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
+    let mut argv: Vec<OsString> = env::args_os().collect();
+    let docker_helper = argv
+        .first()
+        .and_then(|program| Path::new(program).file_stem())
+        .is_some_and(|name| name == DOCKER_HELPER_NAME);
+    if docker_helper {
+        argv.insert(1, "docker-credential".into());
+    }
+    let args = Args::parse_from(argv);
     let config_manager = ConfigManager::new();
-    let token_manager = TokenManager::new();
     let config_path = config_manager.get_config_path();
-    let mut config = config_manager.read_config(&config_path).unwrap_or_default();
+    let mut config = match config_manager.read_config(&config_path) {
+        Ok(config) => config.unwrap_or_default(),
+        Err(e) => exit_with(e.into()),
+    };
+    let expiry_skew = config
+        .expiry_skew
+        .map_or(DEFAULT_EXPIRY_SKEW, Duration::from_secs);
+    let token_manager = TokenManager::new()
+        .with_expiry_skew(expiry_skew)
+        .with_interactive(!args.no_interactive)
+        .with_warning_hook(|warning| eprintln!("Warning: {warning}"));
+    #[cfg(unix)]
+    let token_manager = match std::env::var_os(AGENT_SOCK_ENV) {
+        Some(socket) if !socket.is_empty() => token_manager.with_agent_socket(socket.into()),
+        _ => token_manager,
+    };
     let credentials_provider = ConsoleCredentialsProvider;
 
     if let Err(e) = run_command(
@@ -72,7 +247,44 @@ async fn main() {
     )
     .await
     {
-        eprintln!("{e}");
+        // Docker reads a credential helper's errors from stdout.
+        if docker_helper {
+            println!("{e}");
+            std::process::exit(e.exit_code());
+        }
+        exit_with(e);
+    }
+}
+
+fn exit_with(err: TokensError) -> ! {
+    // The child has already reported its own failure.
+    if !matches!(err, TokensError::ChildExited(_)) {
+        eprintln!("{err}");
+    }
+    std::process::exit(err.exit_code());
+}
+
+/// Accept only names a shell would take as a variable, since the `export` format is
+/// meant to be evaluated.
+fn env_var_name(name: &str) -> Result<String, String> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err("must be letters, digits and underscores, not starting with a digit".to_string())
+    }
+}
+
+/// Reject hosts that would split into extra `.netrc` tokens.
+fn netrc_machine(host: &str) -> Result<String, String> {
+    if !host.is_empty() && !host.contains(char::is_whitespace) {
+        Ok(host.to_string())
+    } else {
+        Err("must be a host name without whitespace".to_string())
     }
 }
 
@@ -81,8 +293,8 @@ async fn run_command(
     config: &mut ConfigFile,
     config_manager: &ConfigManager,
     token_manager: &TokenManager,
-    credentials_provider: &dyn types::CredentialsProvider,
-) -> Result<(), Box<dyn std::error::Error>> {
+    credentials_provider: &dyn CredentialsProvider,
+) -> Result<(), TokensError> {
     let context = CommandContext {
         config,
         config_manager,
@@ -96,12 +308,14 @@ async fn run_command(
             auth_url,
             client_id,
             secret,
+            grant_type,
         } => {
             let command = AddCommand {
                 nickname,
                 auth_url,
                 client_id,
                 secret,
+                grant_type,
             };
             command.execute(context).await
         }
@@ -114,12 +328,38 @@ async fn run_command(
             refresh_token,
             format,
             scopes,
+            verify,
+            env_var,
+            machine,
+            template,
+            template_file,
+            template_name,
         } => {
+            let template = template
+                .map(TemplateSource::Inline)
+                .or(template_file.map(TemplateSource::File))
+                .or(template_name.map(TemplateSource::Named));
             let command = GetCommand {
                 nickname,
                 refresh_token,
                 format,
                 scopes,
+                verify,
+                env_var,
+                machine,
+                template,
+            };
+            command.execute(context).await
+        }
+        Command::Inspect {
+            nickname,
+            scopes,
+            verify,
+        } => {
+            let command = InspectCommand {
+                nickname,
+                scopes,
+                verify,
             };
             command.execute(context).await
         }
@@ -127,8 +367,85 @@ async fn run_command(
             let command = DeleteCommand { nickname };
             command.execute(context).await
         }
-        Command::Logout { nickname } => {
-            let command = LogoutCommand { nickname };
+        Command::Logout { nickname, local } => {
+            let command = LogoutCommand { nickname, local };
+            command.execute(context).await
+        }
+        Command::Exec {
+            nickname,
+            env_var,
+            refresh_token_var,
+            header_var,
+            scopes,
+            command,
+        } => {
+            let command = ExecCommand {
+                nickname,
+                env_var,
+                refresh_token_var,
+                header_var,
+                scopes,
+                command,
+            };
+            command.execute(context).await
+        }
+        Command::Request {
+            nickname,
+            method,
+            url,
+            headers,
+            body,
+            scopes,
+            raw,
+        } => {
+            let command = RequestCommand {
+                nickname,
+                method,
+                url,
+                headers,
+                body,
+                scopes,
+                raw,
+            };
+            command.execute(context).await
+        }
+        Command::Proxy {
+            nickname,
+            listen,
+            upstream,
+            scopes,
+        } => {
+            let command = ProxyCommand {
+                nickname,
+                listen,
+                upstream,
+                scopes,
+            };
+            command.execute(context).await
+        }
+        #[cfg(unix)]
+        Command::Agent { socket } => {
+            let command = AgentCommand { socket };
+            command.execute(context).await
+        }
+        Command::KubeCredential { nickname, scopes } => {
+            let command = KubeCredentialCommand { nickname, scopes };
+            command.execute(context).await
+        }
+        Command::GitCredential { action } => {
+            let command = GitCredentialCommand { action };
+            command.execute(context).await
+        }
+        Command::DockerCredential { action } => {
+            let command = DockerCredentialCommand { action };
+            command.execute(context).await
+        }
+        Command::Template { action } => {
+            let command = TemplateCommand { action };
+            command.execute(context).await
+        }
+        Command::Vault { action } => {
+            let command = VaultCommand { action };
             command.execute(context).await
         }
     }
@@ -137,10 +454,11 @@ async fn run_command(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::tempdir;
 
     struct MockCredentialsProvider;
 
-    impl types::CredentialsProvider for MockCredentialsProvider {
+    impl CredentialsProvider for MockCredentialsProvider {
         fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
             Ok(("test_user".to_string(), "test_pass".to_string()))
         }
@@ -150,11 +468,12 @@ mod tests {
         let mut config = ConfigFile::default();
         config.clients.insert(
             "test_client".to_string(),
-            types::AuthConfig {
+            tokens::AuthConfig {
                 client_id: "test_id".to_string(),
                 auth_url: "https://example.com/auth".to_string(),
                 refresh_token: None,
                 secret: None,
+                ..Default::default()
             },
         );
         config
@@ -163,16 +482,19 @@ mod tests {
     #[tokio::test]
     async fn test_run_command_add() {
         let mut config = ConfigFile::default();
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let credentials_provider = MockCredentialsProvider;
 
         let args = Args {
+            no_interactive: false,
             cmd: Command::Add {
                 nickname: Some("test".to_string()),
                 auth_url: "https://example.com/auth".to_string(),
                 client_id: "test_client".to_string(),
                 secret: None,
+                grant_type: GrantType::Password,
             },
         };
 
@@ -190,11 +512,15 @@ mod tests {
     #[tokio::test]
     async fn test_run_command_list() {
         let mut config = create_test_config();
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let credentials_provider = MockCredentialsProvider;
 
-        let args = Args { cmd: Command::List };
+        let args = Args {
+            cmd: Command::List,
+            no_interactive: false,
+        };
 
         let result = run_command(
             args,
@@ -210,11 +536,13 @@ mod tests {
     #[tokio::test]
     async fn test_run_command_delete() {
         let mut config = create_test_config();
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let credentials_provider = MockCredentialsProvider;
 
         let args = Args {
+            no_interactive: false,
             cmd: Command::Delete {
                 nickname: "test_client".to_string(),
             },
@@ -234,13 +562,16 @@ mod tests {
     #[tokio::test]
     async fn test_run_command_logout() {
         let mut config = create_test_config();
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let credentials_provider = MockCredentialsProvider;
 
         let args = Args {
+            no_interactive: false,
             cmd: Command::Logout {
                 nickname: "test_client".to_string(),
+                local: false,
             },
         };
 
@@ -255,19 +586,52 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_get_rejects_invalid_env_var_names() {
+        let parse = |name: &str| {
+            Args::try_parse_from(["tokens", "get", "client", "-f", "export", "-e", name])
+        };
+
+        assert!(parse("API_TOKEN").is_ok());
+        assert!(parse("_token2").is_ok());
+        for name in ["", "2FA", "X;rm -rf ~", "A-B", "TÖKEN"] {
+            assert!(parse(name).is_err(), "{name:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn test_get_rejects_machines_with_whitespace() {
+        let parse = |host: &str| {
+            Args::try_parse_from(["tokens", "get", "client", "-f", "netrc", "--machine", host])
+        };
+
+        assert!(parse("api.internal").is_ok());
+        for host in ["", "api.internal login evil", "a\tb", "a\nmachine b"] {
+            assert!(parse(host).is_err(), "{host:?} was accepted");
+        }
+    }
+
     #[tokio::test]
     async fn test_run_command_get() {
         let mut config = create_test_config();
-        let config_manager = ConfigManager::new();
+        let dir = tempdir().unwrap();
+        let config_manager = ConfigManager::with_path(dir.path().join("config.json"));
         let token_manager = TokenManager::new();
         let credentials_provider = MockCredentialsProvider;
 
         let args = Args {
+            no_interactive: false,
             cmd: Command::Get {
                 nickname: "test_client".to_string(),
                 refresh_token: false,
                 format: None,
                 scopes: vec![],
+                verify: false,
+                env_var: "TOKEN".to_string(),
+                machine: None,
+                template: None,
+                template_file: None,
+                template_name: None,
             },
         };
 
@@ -1,17 +1,268 @@
-use crate::types::{AuthConfig, CredentialsProvider, TokenResponse};
-use reqwest::Client;
+use crate::config::ConfigManager;
+use crate::error::TokensError;
+use crate::types::{
+    AuthConfig, CachedToken, ConfigFile, CredentialsProvider, DeviceAuthorizationResponse,
+    GrantType, ProviderMetadata, TokenResponse, unix_now,
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::JwkSet;
+use reqwest::{Client, Response, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// How long to wait for the browser to complete an authorization code login.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Polling interval when the device authorization response does not specify one.
+const DEFAULT_DEVICE_INTERVAL: u64 = 5;
+
+/// Default margin before expiry at which a cached access token is refreshed.
+pub const DEFAULT_EXPIRY_SKEW: Duration = Duration::from_secs(30);
+
+/// Attempts at a token refresh that fails transiently, e.g. on a timeout or a 503.
+const REFRESH_ATTEMPTS: u32 = 3;
+
+/// Delay before the first refresh retry, doubled after each attempt.
+const REFRESH_BACKOFF: Duration = Duration::from_millis(500);
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Outcome of a single device token poll.
+#[derive(Debug, PartialEq)]
+enum DevicePoll {
+    Token(String),
+    Pending,
+    SlowDown,
+}
+
+/// Error response from a token or authorization endpoint (RFC 6749 section 5.2).
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct OAuthError {
+    pub error: String,
+    pub error_description: Option<String>,
+    pub error_uri: Option<String>,
+}
+
+impl OAuthError {
+    /// Whether logging in again could help, rather than the client being misconfigured.
+    pub fn is_invalid_grant(&self) -> bool {
+        self.error == "invalid_grant"
+    }
+}
+
+impl fmt::Display for OAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(description) = &self.error_description {
+            write!(f, ": {description}")?;
+        }
+        if let Some(uri) = &self.error_uri {
+            write!(f, " ({uri})")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for OAuthError {}
+
+type WarningHook = Box<dyn Fn(&str) + Send + Sync>;
 
-#[derive(Default)]
 pub struct TokenManager {
     client: Client,
+    expiry_skew: Duration,
+    interactive: bool,
+    retry_backoff: Duration,
+    agent_socket: Option<PathBuf>,
+    on_warning: Option<WarningHook>,
+}
+
+impl Default for TokenManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TokenManager {
     pub fn new() -> Self {
         Self {
             client: Client::new(),
+            expiry_skew: DEFAULT_EXPIRY_SKEW,
+            interactive: true,
+            retry_backoff: REFRESH_BACKOFF,
+            agent_socket: None,
+            on_warning: None,
+        }
+    }
+
+    /// Reuse cached access tokens until `skew` before they expire.
+    pub fn with_expiry_skew(mut self, skew: Duration) -> Self {
+        self.expiry_skew = skew;
+        self
+    }
+
+    /// Margin before expiry at which a cached access token is no longer reused.
+    pub fn expiry_skew(&self) -> Duration {
+        self.expiry_skew
+    }
+
+    /// Whether a login may prompt the user when no refresh token is usable.
+    /// When disabled, such logins fail with [`TokensError::LoginRequired`].
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Ask the `tokens agent` listening on `socket` for tokens before falling back
+    /// to refreshing them locally.
+    pub fn with_agent_socket(mut self, socket: PathBuf) -> Self {
+        self.agent_socket = Some(socket);
+        self
+    }
+
+    pub fn agent_socket(&self) -> Option<&Path> {
+        self.agent_socket.as_deref()
+    }
+
+    /// Call `hook` with problems that don't fail the request, such as a refresh that
+    /// is being retried or a rejected refresh token that leads to a new login.
+    pub fn with_warning_hook(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.on_warning = Some(Box::new(hook));
+        self
+    }
+
+    fn warn(&self, message: &str) {
+        if let Some(hook) = &self.on_warning {
+            hook(message);
+        }
+    }
+
+    #[cfg(test)]
+    fn with_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Resolve the client's endpoints, fetching and caching its discovery document if needed.
+    /// Falls back to Keycloak's layout under `auth_url` when discovery is unavailable.
+    pub async fn endpoints(&self, auth: &mut AuthConfig) -> ProviderMetadata {
+        if let Some(discovery) = &auth.discovery {
+            return discovery.clone();
+        }
+
+        let issuer = auth.auth_url.trim_end_matches('/');
+        let url = format!("{issuer}/.well-known/openid-configuration");
+        let discovered = async {
+            self.client
+                .get(&url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<ProviderMetadata>()
+                .await
+        };
+
+        match discovered.await {
+            Ok(metadata) => {
+                auth.discovery = Some(metadata.clone());
+                metadata
+            }
+            Err(_) => ProviderMetadata::keycloak(&auth.auth_url),
+        }
+    }
+
+    /// Fetch a token for the client stored as `nickname` in `config` and persist any
+    /// rotated refresh token or cached access token.
+    ///
+    /// The config lock is held while a cached or refreshed token is obtained, and the
+    /// client is re-read from disk first, so concurrent invocations never refresh with
+    /// a token another process has already rotated, nor overwrite its result with a
+    /// stale one. Interactive logins run without the lock and are merged back after.
+    ///
+    /// With an agent socket, the agent is asked first; the token is fetched locally
+    /// if the agent can't be reached or needs an interactive login.
+    pub async fn acquire(
+        &self,
+        config_manager: &ConfigManager,
+        config: &mut ConfigFile,
+        nickname: &str,
+        fetch_refresh_token: bool,
+        scopes: &[String],
+        credentials_provider: &dyn CredentialsProvider,
+    ) -> Result<String, TokensError> {
+        #[cfg(unix)]
+        if let Some(socket) = self.agent_socket() {
+            let request = crate::agent::AgentRequest {
+                nickname: nickname.to_string(),
+                refresh_token: fetch_refresh_token,
+                scopes: scopes.to_vec(),
+            };
+            match crate::agent::request(socket, &request).await {
+                Ok(response) => match response.into_result(nickname) {
+                    Err(TokensError::LoginRequired) => {}
+                    result => return result,
+                },
+                Err(err) => self.warn(&format!(
+                    "failed to reach agent at {}: {err}",
+                    socket.display()
+                )),
+            }
+        }
+
+        let config_path = config_manager.get_config_path();
+        let mut lock = config_manager.lock_async(&config_path).await?;
+
+        if let Some(latest) = lock
+            .read()?
+            .and_then(|mut config| config.clients.remove(nickname))
+        {
+            config.clients.insert(nickname.to_string(), latest);
+        }
+
+        let auth = config_manager
+            .get_client_mut(config, nickname)
+            .ok_or_else(|| TokensError::ClientNotFound(nickname.to_string()))?;
+        let before = auth.clone();
+
+        let failed = |err| TokensError::from(err).context("Failed to retrieve token");
+        let token = match self
+            .reuse_or_refresh(auth, fetch_refresh_token, scopes)
+            .await
+            .map_err(failed)?
+        {
+            Some(token) => token,
+            None => {
+                // A browser login or prompt can take minutes; other processes shouldn't
+                // wait on it. The result is merged back under the lock below.
+                drop(lock);
+                let token = self
+                    .login(auth, scopes, credentials_provider)
+                    .await
+                    .map_err(failed)?;
+                lock = config_manager.lock_async(&config_path).await?;
+                token
+            }
+        };
+
+        // Reusing a cached token changes nothing, so leave the config and its backup alone.
+        if *auth == before {
+            return Ok(token);
+        }
+
+        let auth = auth.clone();
+        if let Err(err) = lock.update(|config| {
+            config.clients.insert(nickname.to_string(), auth);
+        }) {
+            self.warn(&format!("token retrieved but failed to save config: {err}"));
         }
+
+        Ok(token)
     }
 
     pub async fn get_or_refresh_token(
@@ -21,19 +272,83 @@ impl TokenManager {
         scopes: &[String],
         credentials_provider: &dyn CredentialsProvider,
     ) -> Result<String, Box<dyn Error>> {
+        match self
+            .reuse_or_refresh(auth, fetch_refresh_token, scopes)
+            .await?
+        {
+            Some(token) => Ok(token),
+            None => self.login(auth, scopes, credentials_provider).await,
+        }
+    }
+
+    /// Get a token without logging in: the cached access token, a client credentials
+    /// token, or one from the refresh token. `None` means a login is needed.
+    pub async fn reuse_or_refresh(
+        &self,
+        auth: &mut AuthConfig,
+        fetch_refresh_token: bool,
+        scopes: &[String],
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        if !fetch_refresh_token
+            && scopes.is_empty()
+            && let Some(cached) = &auth.access_token
+            && cached.is_valid(self.expiry_skew)
+        {
+            return Ok(Some(cached.access_token.clone()));
+        }
+
+        if auth.grant_type == GrantType::ClientCredentials {
+            if fetch_refresh_token {
+                return Err("The client credentials grant does not issue refresh tokens.".into());
+            }
+            return self
+                .request_client_credentials_token(auth, scopes)
+                .await
+                .map(Some);
+        }
+
         if let Some(ref refresh_token) = auth.refresh_token.clone()
-            && let Ok(token) = self.use_refresh_token(auth, refresh_token, scopes).await
+            && !auth.refresh_token_expired()
         {
-            return if fetch_refresh_token {
-                Ok(refresh_token.clone())
-            } else {
-                Ok(token)
-            };
+            match self.refresh_with_retry(auth, refresh_token, scopes).await {
+                Ok(_) if fetch_refresh_token => return Ok(Some(refresh_token.clone())),
+                Ok(token) => return Ok(Some(token)),
+                // Only a rejected grant means logging in again can help; anything else
+                // would fail the same way or hang a non-interactive job on a prompt.
+                Err(e)
+                    if e.downcast_ref::<OAuthError>()
+                        .is_some_and(OAuthError::is_invalid_grant) =>
+                {
+                    self.warn(&format!("refresh token was not accepted: {e}"));
+                }
+                Err(e) => return Err(e),
+            }
         }
 
-        let (username, password) = credentials_provider.get_credentials()?;
-        self.request_new_token(auth, &username, &password, scopes)
-            .await
+        Ok(None)
+    }
+
+    /// Log in with the client's grant type, prompting or opening a browser as needed.
+    pub async fn login(
+        &self,
+        auth: &mut AuthConfig,
+        scopes: &[String],
+        credentials_provider: &dyn CredentialsProvider,
+    ) -> Result<String, Box<dyn Error>> {
+        if !self.interactive {
+            return Err(TokensError::LoginRequired.into());
+        }
+
+        match auth.grant_type {
+            GrantType::Password => {
+                let (username, password) = credentials_provider.get_credentials()?;
+                self.request_new_token(auth, &username, &password, scopes)
+                    .await
+            }
+            GrantType::AuthorizationCode => self.authorization_code_login(auth, scopes).await,
+            GrantType::DeviceCode => self.device_code_login(auth, scopes).await,
+            GrantType::ClientCredentials => unreachable!("handled above"),
+        }
     }
 
     async fn request_new_token(
@@ -43,6 +358,7 @@ impl TokenManager {
         password: &str,
         scopes: &[String],
     ) -> Result<String, Box<dyn Error>> {
+        let endpoints = self.endpoints(auth).await;
         let mut form = vec![
             ("grant_type", "password"),
             ("client_id", &auth.client_id),
@@ -52,29 +368,243 @@ impl TokenManager {
 
         self.add_optional_fields(&mut form, auth, scopes);
 
-        let url = format!("{}/protocol/openid-connect/token", auth.auth_url);
         let res = self
             .client
-            .post(&url)
+            .post(&endpoints.token_endpoint)
             .form(&form)
             .send()
-            .await?
-            .error_for_status()?;
+            .await?;
+        let res = error_for_oauth_status(res).await?;
 
         let data: TokenResponse = res.json().await?;
-        if let Some(refresh) = &data.refresh_token {
-            auth.refresh_token = Some(refresh.clone());
+        store_token_response(auth, &data, scopes);
+
+        Ok(data.access_token)
+    }
+
+    /// Fetch a service-account token. No refresh token is kept, so every call re-fetches.
+    async fn request_client_credentials_token(
+        &self,
+        auth: &mut AuthConfig,
+        scopes: &[String],
+    ) -> Result<String, Box<dyn Error>> {
+        if auth.secret.is_none() {
+            return Err("The client credentials grant requires a client secret.".into());
         }
 
+        let endpoints = self.endpoints(auth).await;
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", auth.client_id.as_str()),
+        ];
+
+        self.add_optional_fields(&mut form, auth, scopes);
+
+        let res = self
+            .client
+            .post(&endpoints.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        let res = error_for_oauth_status(res).await?;
+
+        let data: TokenResponse = res.json().await?;
+        cache_access_token(auth, &data, scopes);
+
+        Ok(data.access_token)
+    }
+
+    /// Log in through the browser, catching the redirect on a loopback listener.
+    async fn authorization_code_login(
+        &self,
+        auth: &mut AuthConfig,
+        scopes: &[String],
+    ) -> Result<String, Box<dyn Error>> {
+        let endpoints = self.endpoints(auth).await;
+        let authorization_endpoint = endpoints
+            .authorization_endpoint
+            .ok_or("The issuer does not advertise an authorization endpoint.")?;
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let redirect_uri = format!(
+            "http://127.0.0.1:{}/callback",
+            listener.local_addr()?.port()
+        );
+        let pkce = Pkce::generate();
+        let state = random_urlsafe_string();
+
+        let authorize_url = authorize_url(
+            &authorization_endpoint,
+            &auth.client_id,
+            &redirect_uri,
+            &pkce,
+            &state,
+            scopes,
+        )?;
+        eprintln!("Open the following URL in your browser to log in:\n\n{authorize_url}\n");
+
+        let code = tokio::time::timeout(LOGIN_TIMEOUT, wait_for_redirect(&listener, &state))
+            .await
+            .map_err(|_| "Timed out waiting for the browser login to complete.")??;
+
+        self.exchange_authorization_code(auth, &code, &redirect_uri, &pkce.verifier, scopes)
+            .await
+    }
+
+    async fn exchange_authorization_code(
+        &self,
+        auth: &mut AuthConfig,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+        scopes: &[String],
+    ) -> Result<String, Box<dyn Error>> {
+        let endpoints = self.endpoints(auth).await;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("client_id", &auth.client_id),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", code_verifier),
+        ];
+
+        self.add_optional_fields(&mut form, auth, &[]);
+
+        let res = self
+            .client
+            .post(&endpoints.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        let res = error_for_oauth_status(res).await?;
+
+        let data: TokenResponse = res.json().await?;
+        // The code was issued for the scopes sent to the authorization endpoint.
+        store_token_response(auth, &data, scopes);
+
         Ok(data.access_token)
     }
 
+    /// Log in on another device, polling until the user approves (RFC 8628).
+    async fn device_code_login(
+        &self,
+        auth: &mut AuthConfig,
+        scopes: &[String],
+    ) -> Result<String, Box<dyn Error>> {
+        let endpoints = self.endpoints(auth).await;
+        let device_endpoint = endpoints
+            .device_authorization_endpoint
+            .ok_or("The issuer does not advertise a device authorization endpoint.")?;
+
+        let mut form = vec![("client_id", auth.client_id.as_str())];
+        self.add_optional_fields(&mut form, auth, scopes);
+
+        let device = self
+            .client
+            .post(&device_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        let device = error_for_oauth_status(device).await?;
+        let device: DeviceAuthorizationResponse = device.json().await?;
+
+        match &device.verification_uri_complete {
+            Some(uri) => eprintln!(
+                "To log in, visit {uri}\nor go to {} and enter the code: {}",
+                device.verification_uri, device.user_code
+            ),
+            None => eprintln!(
+                "To log in, visit {} and enter the code: {}",
+                device.verification_uri, device.user_code
+            ),
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(device.expires_in);
+        let mut interval = device.interval.unwrap_or(DEFAULT_DEVICE_INTERVAL);
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            if Instant::now() >= deadline {
+                return Err("Device code expired before the login was completed.".into());
+            }
+
+            match self
+                .poll_device_token(auth, &endpoints.token_endpoint, &device.device_code, scopes)
+                .await?
+            {
+                DevicePoll::Token(token) => return Ok(token),
+                DevicePoll::Pending => {}
+                DevicePoll::SlowDown => interval += 5,
+            }
+        }
+    }
+
+    async fn poll_device_token(
+        &self,
+        auth: &mut AuthConfig,
+        token_endpoint: &str,
+        device_code: &str,
+        scopes: &[String],
+    ) -> Result<DevicePoll, Box<dyn Error>> {
+        let mut form = vec![
+            ("grant_type", DEVICE_CODE_GRANT),
+            ("client_id", &auth.client_id),
+            ("device_code", device_code),
+        ];
+
+        self.add_optional_fields(&mut form, auth, &[]);
+
+        let res = self.client.post(token_endpoint).form(&form).send().await?;
+
+        let res = match error_for_oauth_status(res).await {
+            Ok(res) => res,
+            Err(e) => {
+                return match e.downcast_ref::<OAuthError>().map(|e| e.error.as_str()) {
+                    Some("authorization_pending") => Ok(DevicePoll::Pending),
+                    Some("slow_down") => Ok(DevicePoll::SlowDown),
+                    _ => Err(e),
+                };
+            }
+        };
+
+        let data: TokenResponse = res.json().await?;
+        // The device code was issued for the scopes sent with the authorization request.
+        store_token_response(auth, &data, scopes);
+
+        Ok(DevicePoll::Token(data.access_token))
+    }
+
+    /// Refresh the access token, retrying with backoff while the failure looks transient.
+    async fn refresh_with_retry(
+        &self,
+        auth: &mut AuthConfig,
+        refresh_token: &str,
+        scopes: &[String],
+    ) -> Result<String, Box<dyn Error>> {
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.use_refresh_token(auth, refresh_token, scopes).await {
+                Err(e) if attempt < REFRESH_ATTEMPTS && is_transient(e.as_ref()) => {
+                    self.warn(&format!(
+                        "token refresh failed, retrying in {backoff:?}: {e}"
+                    ));
+                }
+                result => return result,
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
     async fn use_refresh_token(
         &self,
         auth: &mut AuthConfig,
         refresh_token: &str,
         scopes: &[String],
-    ) -> Result<String, reqwest::Error> {
+    ) -> Result<String, Box<dyn Error>> {
+        let endpoints = self.endpoints(auth).await;
         let mut form = vec![
             ("grant_type", "refresh_token"),
             ("client_id", &auth.client_id),
@@ -83,25 +613,68 @@ impl TokenManager {
 
         self.add_optional_fields(&mut form, auth, scopes);
 
-        let url = format!("{}/protocol/openid-connect/token", auth.auth_url);
-
         let res = self
             .client
-            .post(&url)
+            .post(&endpoints.token_endpoint)
             .form(&form)
             .send()
-            .await?
-            .error_for_status()?;
+            .await?;
+        let res = error_for_oauth_status(res).await?;
 
         let data: TokenResponse = res.json().await?;
-
-        if let Some(refresh) = &data.refresh_token {
-            auth.refresh_token = Some(refresh.clone());
-        }
+        store_token_response(auth, &data, scopes);
 
         Ok(data.access_token)
     }
 
+    /// Fetch the issuer's published signing keys.
+    pub async fn fetch_jwks(&self, auth: &mut AuthConfig) -> Result<JwkSet, Box<dyn Error>> {
+        let jwks_uri = self
+            .endpoints(auth)
+            .await
+            .jwks_uri
+            .ok_or("The issuer does not advertise a JWKS URI.")?;
+
+        let jwks = self
+            .client
+            .get(&jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(jwks)
+    }
+
+    /// Revoke the stored refresh token at the issuer (RFC 7009), falling back to the
+    /// end-session endpoint for providers that do not advertise revocation.
+    pub async fn revoke_refresh_token(&self, auth: &mut AuthConfig) -> Result<(), Box<dyn Error>> {
+        let Some(refresh_token) = auth.refresh_token.clone() else {
+            return Ok(());
+        };
+
+        let endpoints = self.endpoints(auth).await;
+        let mut form = vec![("client_id", auth.client_id.as_str())];
+        let url = if let Some(url) = endpoints.revocation_endpoint {
+            form.push(("token", &refresh_token));
+            form.push(("token_type_hint", "refresh_token"));
+            url
+        } else if let Some(url) = endpoints.end_session_endpoint {
+            form.push(("refresh_token", &refresh_token));
+            url
+        } else {
+            return Err("The issuer does not advertise a revocation or logout endpoint.".into());
+        };
+
+        self.add_optional_fields(&mut form, auth, &[]);
+
+        let res = self.client.post(&url).form(&form).send().await?;
+        error_for_oauth_status(res).await?;
+
+        Ok(())
+    }
+
     fn add_optional_fields<'a>(
         &self,
         form: &mut Vec<(&str, &'a str)>,
@@ -119,6 +692,165 @@ impl TokenManager {
     }
 }
 
+/// Like `error_for_status`, but keeps the endpoint's [`OAuthError`] body when it sent one.
+async fn error_for_oauth_status(res: Response) -> Result<Response, Box<dyn Error>> {
+    let Err(status_error) = res.error_for_status_ref() else {
+        return Ok(res);
+    };
+
+    match res.json::<OAuthError>().await {
+        Ok(oauth_error) => Err(oauth_error.into()),
+        Err(_) => Err(status_error.into()),
+    }
+}
+
+/// Whether a failed request may succeed if retried: the server couldn't be reached,
+/// timed out, or reported itself overloaded or unavailable.
+fn is_transient(err: &(dyn Error + 'static)) -> bool {
+    if let Some(err) = err.downcast_ref::<OAuthError>() {
+        return matches!(
+            err.error.as_str(),
+            "server_error" | "temporarily_unavailable"
+        );
+    }
+
+    err.downcast_ref::<reqwest::Error>().is_some_and(|err| {
+        err.is_timeout()
+            || err.is_connect()
+            || err
+                .status()
+                .is_some_and(|s| s.is_server_error() || s.as_u16() == 429)
+    })
+}
+
+/// Record the refresh token and, for default-scope requests, the access token and expiries.
+fn store_token_response(auth: &mut AuthConfig, data: &TokenResponse, scopes: &[String]) {
+    if let Some(refresh) = &data.refresh_token {
+        auth.refresh_token = Some(refresh.clone());
+        // Keycloak reports offline tokens, which never expire, as `refresh_expires_in: 0`.
+        auth.refresh_token_expires_at = data
+            .refresh_expires_in
+            .filter(|&secs| secs > 0)
+            .map(|secs| unix_now() + secs);
+    }
+    cache_access_token(auth, data, scopes);
+}
+
+/// Only tokens issued with the client's default scopes are cached, so a scoped
+/// request never hides or replaces the token a plain `get` would return.
+fn cache_access_token(auth: &mut AuthConfig, data: &TokenResponse, scopes: &[String]) {
+    if !scopes.is_empty() {
+        return;
+    }
+    auth.access_token = data.expires_in.map(|secs| CachedToken {
+        access_token: data.access_token.clone(),
+        expires_at: unix_now() + secs,
+        id_token: data.id_token.clone(),
+        scope: data.scope.clone(),
+    });
+}
+
+fn authorize_url(
+    authorization_endpoint: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    pkce: &Pkce,
+    state: &str,
+    scopes: &[String],
+) -> Result<Url, Box<dyn Error>> {
+    let mut url = Url::parse(authorization_endpoint)?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("code_challenge", &pkce.challenge)
+        .append_pair("code_challenge_method", "S256")
+        .append_pair("state", state);
+
+    if !scopes.is_empty() {
+        url.query_pairs_mut()
+            .append_pair("scope", &scopes.join(" "));
+    }
+
+    Ok(url)
+}
+
+/// PKCE code verifier and its S256 challenge (RFC 7636).
+struct Pkce {
+    verifier: String,
+    challenge: String,
+}
+
+impl Pkce {
+    fn generate() -> Self {
+        let verifier = random_urlsafe_string();
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Self {
+            verifier,
+            challenge,
+        }
+    }
+}
+
+fn random_urlsafe_string() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// Accept connections until the authorization server redirects back with a code.
+async fn wait_for_redirect(listener: &TcpListener, state: &str) -> Result<String, Box<dyn Error>> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+
+        let mut buf = vec![0; 8192];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let target = request
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .unwrap_or("/");
+
+        let url = Url::parse(&format!("http://127.0.0.1{target}"))?;
+        if url.path() != "/callback" {
+            stream
+                .write_all(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await?;
+            continue;
+        }
+
+        let param = |key: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        let result = if let Some(error) = param("error") {
+            let description = param("error_description").unwrap_or_default();
+            Err(format!("Login failed: {error} {description}")
+                .trim_end()
+                .to_string())
+        } else if param("state").as_deref() != Some(state) {
+            Err("Login failed: state mismatch in redirect.".to_string())
+        } else {
+            param("code").ok_or_else(|| "Login failed: no code in redirect.".to_string())
+        };
+
+        let message = match &result {
+            Ok(_) => "Login complete. You can close this window.",
+            Err(e) => e.as_str(),
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+            message.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+
+        return Ok(result?);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,6 +865,14 @@ mod tests {
         }
     }
 
+    struct FailingCredentialsProvider;
+
+    impl CredentialsProvider for FailingCredentialsProvider {
+        fn get_credentials(&self) -> Result<(String, String), Box<dyn Error>> {
+            Err("should not prompt".into())
+        }
+    }
+
     #[tokio::test]
     async fn ensure_scopes() {
         let mock_response = r#"{"access_token": "token", "refresh_token": "refresh"}"#;
@@ -152,6 +892,7 @@ mod tests {
             client_id: "test".to_string(),
             refresh_token: None,
             secret: None,
+            ..Default::default()
         };
 
         let token_manager = TokenManager::new();
@@ -186,6 +927,7 @@ mod tests {
             client_id: "test".to_string(),
             refresh_token: None,
             secret: Some("secret".to_string()),
+            ..Default::default()
         };
 
         let token_manager = TokenManager::new();
@@ -200,4 +942,655 @@ mod tests {
         assert_eq!(token, "token");
         mock.assert_async().await;
     }
+
+    #[test]
+    fn pkce_challenge_matches_verifier() {
+        let pkce = Pkce::generate();
+        let expected = URL_SAFE_NO_PAD.encode(Sha256::digest(pkce.verifier.as_bytes()));
+
+        assert_eq!(pkce.verifier.len(), 43);
+        assert_eq!(pkce.challenge, expected);
+    }
+
+    #[tokio::test]
+    async fn redirect_returns_code() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let browser = tokio::spawn(async move {
+            let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .unwrap();
+            stream
+                .write_all(b"GET /callback?code=abc&state=xyz HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        });
+
+        let code = wait_for_redirect(&listener, "xyz").await.unwrap();
+        assert_eq!(code, "abc");
+        assert!(browser.await.unwrap().starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn redirect_rejects_state_mismatch() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .unwrap();
+            stream
+                .write_all(b"GET /callback?code=abc&state=other HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        assert!(wait_for_redirect(&listener, "xyz").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ensure_authorization_code_exchange() {
+        let mock_response = r#"{"access_token": "token", "refresh_token": "refresh"}"#;
+        let mut server = Server::new_async().await;
+
+        let mock = server
+            .mock("POST", "/realms/master/protocol/openid-connect/token")
+            .with_status(200)
+            .match_body(Regex(
+                "grant_type=authorization_code.*code=abc.*code_verifier=verifier".into(),
+            ))
+            .with_header("content-type", "application/json")
+            .with_body(mock_response)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: format!("{}/realms/master", server.url()),
+            client_id: "test".to_string(),
+            grant_type: GrantType::AuthorizationCode,
+            ..Default::default()
+        };
+
+        let token_manager = TokenManager::new();
+        let token = token_manager
+            .exchange_authorization_code(
+                &mut auth,
+                "abc",
+                "http://127.0.0.1/callback",
+                "verifier",
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(token, "token");
+        assert_eq!(auth.refresh_token, Some("refresh".to_string()));
+        mock.assert_async().await;
+    }
+
+    async fn mock_device_poll(status: usize, body: &str) -> Result<DevicePoll, Box<dyn Error>> {
+        let mut server = Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .match_body(Regex("device_code=dev123".into()))
+            .with_status(status)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            grant_type: GrantType::DeviceCode,
+            ..Default::default()
+        };
+
+        let token_endpoint = format!("{}/protocol/openid-connect/token", server.url());
+        TokenManager::new()
+            .poll_device_token(&mut auth, &token_endpoint, "dev123", &[])
+            .await
+    }
+
+    #[tokio::test]
+    async fn device_poll_pending() {
+        let poll = mock_device_poll(400, r#"{"error": "authorization_pending"}"#).await;
+        assert_eq!(poll.unwrap(), DevicePoll::Pending);
+    }
+
+    #[tokio::test]
+    async fn device_poll_slow_down() {
+        let poll = mock_device_poll(400, r#"{"error": "slow_down"}"#).await;
+        assert_eq!(poll.unwrap(), DevicePoll::SlowDown);
+    }
+
+    #[tokio::test]
+    async fn device_poll_denied() {
+        let poll = mock_device_poll(400, r#"{"error": "access_denied"}"#).await;
+        assert!(poll.is_err());
+    }
+
+    #[tokio::test]
+    async fn ensure_device_code_login() {
+        let mut server = Server::new_async().await;
+
+        let device_mock = server
+            .mock("POST", "/protocol/openid-connect/auth/device")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"device_code": "dev123", "user_code": "ABCD-EFGH",
+                    "verification_uri": "https://example.com/device",
+                    "expires_in": 60, "interval": 0}"#,
+            )
+            .create_async()
+            .await;
+
+        let token_mock = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .match_body(Regex("grant_type=urn.*device_code=dev123".into()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"access_token": "token", "refresh_token": "refresh"}"#)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            grant_type: GrantType::DeviceCode,
+            ..Default::default()
+        };
+
+        let token = TokenManager::new()
+            .get_or_refresh_token(&mut auth, false, &[], &MockCredentialsProvider)
+            .await
+            .unwrap();
+
+        assert_eq!(token, "token");
+        assert_eq!(auth.refresh_token, Some("refresh".to_string()));
+        device_mock.assert_async().await;
+        token_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn scoped_device_code_login_is_not_cached() {
+        let mut server = Server::new_async().await;
+
+        let _device = server
+            .mock("POST", "/protocol/openid-connect/auth/device")
+            .match_body(Regex("scope=profile".into()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"device_code": "dev123", "user_code": "ABCD-EFGH",
+                    "verification_uri": "https://example.com/device",
+                    "expires_in": 60, "interval": 0}"#,
+            )
+            .create_async()
+            .await;
+        let _login = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .match_body(Regex("device_code=dev123".into()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"access_token": "scoped", "expires_in": 300, "refresh_token": "refresh"}"#,
+            )
+            .create_async()
+            .await;
+        let refresh = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .match_body(Regex("grant_type=refresh_token".into()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"access_token": "default", "expires_in": 300}"#)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            grant_type: GrantType::DeviceCode,
+            ..Default::default()
+        };
+        let token_manager = TokenManager::new();
+
+        let scoped = token_manager
+            .get_or_refresh_token(
+                &mut auth,
+                false,
+                &["profile".to_string()],
+                &MockCredentialsProvider,
+            )
+            .await
+            .unwrap();
+        assert_eq!(scoped, "scoped");
+        assert_eq!(auth.access_token, None);
+        assert_eq!(auth.refresh_token.as_deref(), Some("refresh"));
+
+        let default = token_manager
+            .get_or_refresh_token(&mut auth, false, &[], &MockCredentialsProvider)
+            .await
+            .unwrap();
+        assert_eq!(default, "default");
+        refresh.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn ensure_client_credentials() {
+        let mut server = Server::new_async().await;
+
+        let mock = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .match_body(Regex(
+                "grant_type=client_credentials.*client_secret=secret".into(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"access_token": "token", "refresh_token": "refresh"}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            secret: Some("secret".to_string()),
+            grant_type: GrantType::ClientCredentials,
+            ..Default::default()
+        };
+
+        let token_manager = TokenManager::new();
+        for _ in 0..2 {
+            let token = token_manager
+                .get_or_refresh_token(&mut auth, false, &[], &FailingCredentialsProvider)
+                .await
+                .unwrap();
+            assert_eq!(token, "token");
+        }
+
+        assert_eq!(auth.refresh_token, None);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn client_credentials_requires_secret() {
+        let mut auth = AuthConfig {
+            auth_url: "http://127.0.0.1:1".to_string(),
+            client_id: "test".to_string(),
+            grant_type: GrantType::ClientCredentials,
+            ..Default::default()
+        };
+
+        let result = TokenManager::new()
+            .get_or_refresh_token(&mut auth, false, &[], &FailingCredentialsProvider)
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn ensure_discovered_token_endpoint() {
+        let mut server = Server::new_async().await;
+
+        let discovery = serde_json::json!({
+            "issuer": server.url(),
+            "token_endpoint": format!("{}/oauth/token", server.url()),
+        });
+        let discovery_mock = server
+            .mock("GET", "/.well-known/openid-configuration")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(discovery.to_string())
+            .expect(1)
+            .create_async()
+            .await;
+
+        let token_mock = server
+            .mock("POST", "/oauth/token")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"access_token": "token", "refresh_token": "refresh"}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        let token_manager = TokenManager::new();
+        for _ in 0..2 {
+            let token = token_manager
+                .get_or_refresh_token(&mut auth, false, &[], &MockCredentialsProvider)
+                .await
+                .unwrap();
+            assert_eq!(token, "token");
+        }
+
+        assert_eq!(
+            auth.discovery.map(|d| d.token_endpoint),
+            Some(format!("{}/oauth/token", server.url()))
+        );
+        discovery_mock.assert_async().await;
+        token_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn ensure_cached_access_token_is_reused() {
+        let mut auth = AuthConfig {
+            auth_url: "http://127.0.0.1:1".to_string(),
+            client_id: "test".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            access_token: Some(CachedToken {
+                access_token: "cached".to_string(),
+                expires_at: unix_now() + 300,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let token = TokenManager::new()
+            .get_or_refresh_token(&mut auth, false, &[], &FailingCredentialsProvider)
+            .await
+            .unwrap();
+
+        assert_eq!(token, "cached");
+    }
+
+    #[tokio::test]
+    async fn ensure_expiring_access_token_is_refreshed() {
+        let mut server = Server::new_async().await;
+
+        let mock = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .match_body(Regex("grant_type=refresh_token".into()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"access_token": "fresh", "refresh_token": "refresh2",
+                    "expires_in": 300, "refresh_expires_in": 1800}"#,
+            )
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            access_token: Some(CachedToken {
+                access_token: "stale".to_string(),
+                expires_at: unix_now() + 10,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let token = TokenManager::new()
+            .with_expiry_skew(Duration::from_secs(30))
+            .get_or_refresh_token(&mut auth, false, &[], &FailingCredentialsProvider)
+            .await
+            .unwrap();
+
+        assert_eq!(token, "fresh");
+        let cached = auth.access_token.unwrap();
+        assert_eq!(cached.access_token, "fresh");
+        assert!(cached.expires_at > unix_now() + 200);
+        assert!(auth.refresh_token_expires_at.unwrap() > unix_now() + 1700);
+        mock.assert_async().await;
+    }
+
+    async fn refresh_rejected_with(body: &str, logins: usize) -> Result<String, Box<dyn Error>> {
+        let mut server = Server::new_async().await;
+
+        let refresh_mock = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .match_body(Regex("grant_type=refresh_token".into()))
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create_async()
+            .await;
+        let password_mock = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .match_body(Regex("grant_type=password".into()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"access_token": "token", "refresh_token": "refresh2"}"#)
+            .expect(logins)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            ..Default::default()
+        };
+
+        let result = TokenManager::new()
+            .get_or_refresh_token(&mut auth, false, &[], &MockCredentialsProvider)
+            .await;
+
+        refresh_mock.assert_async().await;
+        password_mock.assert_async().await;
+        result
+    }
+
+    #[tokio::test]
+    async fn invalid_grant_falls_back_to_login() {
+        let result = refresh_rejected_with(
+            r#"{"error": "invalid_grant", "error_description": "Session not active"}"#,
+            1,
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), "token");
+    }
+
+    #[tokio::test]
+    async fn rejected_client_does_not_fall_back_to_login() {
+        let result = refresh_rejected_with(
+            r#"{"error": "unauthorized_client", "error_description": "Client not allowed",
+                "error_uri": "https://example.com/errors"}"#,
+            0,
+        )
+        .await;
+
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<OAuthError>(),
+            Some(&OAuthError {
+                error: "unauthorized_client".to_string(),
+                error_description: Some("Client not allowed".to_string()),
+                error_uri: Some("https://example.com/errors".to_string()),
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "unauthorized_client: Client not allowed (https://example.com/errors)"
+        );
+    }
+
+    #[tokio::test]
+    async fn transient_refresh_failure_is_retried() {
+        let mut server = Server::new_async().await;
+
+        let unavailable = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .with_status(503)
+            .expect(REFRESH_ATTEMPTS as usize)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            ..Default::default()
+        };
+
+        let warnings = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_warnings = warnings.clone();
+        let result = TokenManager::new()
+            .with_retry_backoff(Duration::from_millis(1))
+            .with_warning_hook(move |warning| {
+                hook_warnings.lock().unwrap().push(warning.to_string())
+            })
+            .get_or_refresh_token(&mut auth, false, &[], &FailingCredentialsProvider)
+            .await;
+
+        // Still failing after every retry, so it gives up rather than prompting.
+        assert!(result.is_err());
+        unavailable.assert_async().await;
+
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), REFRESH_ATTEMPTS as usize - 1);
+        assert!(warnings[0].starts_with("token refresh failed, retrying"));
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_does_not_prompt() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let auth_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let mut auth = AuthConfig {
+            auth_url,
+            client_id: "test".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            ..Default::default()
+        };
+
+        let err = TokenManager::new()
+            .with_retry_backoff(Duration::from_millis(1))
+            .get_or_refresh_token(&mut auth, false, &[], &FailingCredentialsProvider)
+            .await
+            .unwrap_err();
+
+        assert!(is_transient(err.as_ref()), "{err}");
+    }
+
+    #[tokio::test]
+    async fn non_interactive_login_fails_fast() {
+        let mut auth = AuthConfig {
+            auth_url: "http://127.0.0.1:1".to_string(),
+            client_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        let err = TokenManager::new()
+            .with_interactive(false)
+            .get_or_refresh_token(&mut auth, false, &[], &FailingCredentialsProvider)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<TokensError>(),
+            Some(TokensError::LoginRequired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn ensure_scoped_token_is_not_cached() {
+        let mut server = Server::new_async().await;
+
+        let _mock = server
+            .mock("POST", "/protocol/openid-connect/token")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"access_token": "scoped", "expires_in": 300}"#)
+            .create_async()
+            .await;
+
+        let cached = CachedToken {
+            access_token: "cached".to_string(),
+            expires_at: unix_now() + 300,
+            ..Default::default()
+        };
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            access_token: Some(cached.clone()),
+            ..Default::default()
+        };
+
+        let token = TokenManager::new()
+            .get_or_refresh_token(
+                &mut auth,
+                false,
+                &["profile".to_string()],
+                &FailingCredentialsProvider,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(token, "scoped");
+        assert_eq!(auth.access_token, Some(cached));
+    }
+
+    #[tokio::test]
+    async fn ensure_refresh_token_revocation() {
+        let mut server = Server::new_async().await;
+
+        let mock = server
+            .mock("POST", "/protocol/openid-connect/revoke")
+            .match_body(Regex(
+                "token=refresh&token_type_hint=refresh_token.*client_secret=secret".into(),
+            ))
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            secret: Some("secret".to_string()),
+            ..Default::default()
+        };
+
+        TokenManager::new()
+            .revoke_refresh_token(&mut auth)
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn ensure_jwks_fetched_from_issuer() {
+        let mut server = Server::new_async().await;
+
+        let mock = server
+            .mock("GET", "/protocol/openid-connect/certs")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"keys": [{"kty": "OKP", "crv": "Ed25519", "kid": "k1",
+                    "x": "uc6YERLgg1NxRb9iOHAf3ZADIgAmtjt8IZShTGjfW_w"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        let mut auth = AuthConfig {
+            auth_url: server.url(),
+            client_id: "test".to_string(),
+            ..Default::default()
+        };
+
+        let jwks = TokenManager::new().fetch_jwks(&mut auth).await.unwrap();
+
+        assert!(jwks.find("k1").is_some());
+        mock.assert_async().await;
+    }
 }
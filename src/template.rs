@@ -0,0 +1,134 @@
+//! Output templates with `{{placeholder}}` substitution, for `tokens get --template`.
+
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use tokens::ConfigFile;
+use tokens::TokensError;
+
+/// Where a `get --template*` option takes its template from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateSource {
+    Inline(String),
+    File(PathBuf),
+    /// A template stored in the config with `tokens template add`.
+    Named(String),
+}
+
+impl TemplateSource {
+    pub fn load(&self, config: &ConfigFile) -> Result<String, TokensError> {
+        match self {
+            Self::Inline(template) => Ok(template.clone()),
+            Self::File(path) => fs::read_to_string(path).map_err(|e| {
+                TokensError::Other(format!("Failed to read template {}: {e}", path.display()))
+            }),
+            Self::Named(name) => config
+                .templates
+                .get(name)
+                .cloned()
+                .ok_or_else(|| TokensError::Other(format!("No template named '{name}'."))),
+        }
+    }
+}
+
+/// Replace each `{{ name }}` in `template` with `lookup(name)`. Placeholders without
+/// a value are an error, so a typo never silently renders as an empty string.
+///
+/// Values are inserted verbatim; `{{ name | json }}` inserts one as a quoted JSON
+/// string instead, for templates that are themselves JSON.
+pub fn render(
+    template: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, TokensError> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or("Unterminated '{{' in template.")?;
+        let (name, filter) = match after[..end].split_once('|') {
+            Some((name, filter)) => (name.trim(), Some(filter.trim())),
+            None => (after[..end].trim(), None),
+        };
+        let value = lookup(name).ok_or_else(|| {
+            TokensError::Other(format!("No value for '{{{{{name}}}}}' in template."))
+        })?;
+        match filter {
+            None => output.push_str(&value),
+            Some("json") => output.push_str(&serde_json::to_string(&value)?),
+            Some(filter) => {
+                return Err(format!("Unknown filter '{filter}' in template.").into());
+            }
+        }
+        rest = &after[end + 2..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Look up a claim by a dotted path such as `realm_access.roles`. Strings render
+/// bare; anything else renders as JSON.
+pub fn claim(claims: &Value, path: &str) -> Option<String> {
+    let value = path.split('.').try_fold(claims, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        value => value.get(key),
+    })?;
+
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn render_substitutes_placeholders() {
+        let lookup = |name: &str| (name == "access_token").then(|| "abc".to_string());
+
+        assert_eq!(
+            render("authorization: Bearer {{ access_token }}\n", lookup).unwrap(),
+            "authorization: Bearer abc\n"
+        );
+        assert!(render("{{refresh_token}}", lookup).is_err());
+        assert!(render("{{access_token", lookup).is_err());
+    }
+
+    #[test]
+    fn render_json_filter_escapes_values() {
+        let lookup = |name: &str| (name == "sub").then(|| r#"a"b\c"#.to_string());
+
+        assert_eq!(
+            render(r#"{"user": {{ sub | json }}}"#, lookup).unwrap(),
+            r#"{"user": "a\"b\\c"}"#
+        );
+        assert!(render("{{sub | yaml}}", lookup).is_err());
+    }
+
+    #[test]
+    fn claim_follows_dotted_paths() {
+        let claims = json!({
+            "sub": "user-1",
+            "exp": 1700000000,
+            "realm_access": { "roles": ["admin", "dev"] },
+        });
+
+        assert_eq!(claim(&claims, "sub").as_deref(), Some("user-1"));
+        assert_eq!(claim(&claims, "exp").as_deref(), Some("1700000000"));
+        assert_eq!(
+            claim(&claims, "realm_access.roles.1").as_deref(),
+            Some("dev")
+        );
+        assert_eq!(
+            claim(&claims, "realm_access.roles").as_deref(),
+            Some(r#"["admin","dev"]"#)
+        );
+        assert_eq!(claim(&claims, "missing.path"), None);
+    }
+}
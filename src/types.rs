@@ -1,40 +1,196 @@
+use crate::vault::VaultHeader;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How a client obtains a new refresh token when it has none.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy, Default, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[clap(rename_all = "kebab-case")]
+pub enum GrantType {
+    /// Prompt for a username and password (Direct Access Grants).
+    #[default]
+    Password,
+    /// Log in through the browser using Authorization Code + PKCE.
+    AuthorizationCode,
+    /// Log in on another device using the Device Authorization Grant.
+    DeviceCode,
+    /// Authenticate as the client's service account using its secret.
+    ClientCredentials,
+}
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
 pub struct AuthConfig {
     pub auth_url: String,
     pub client_id: String,
     pub refresh_token: Option<String>,
     pub secret: Option<String>,
+    #[serde(default)]
+    pub grant_type: GrantType,
+    /// Cached OpenID Connect discovery document for `auth_url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery: Option<ProviderMetadata>,
+    /// Unix time after which the refresh token is no longer accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token_expires_at: Option<u64>,
+    /// Most recent access token issued without extra scopes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<CachedToken>,
+}
+
+impl AuthConfig {
+    /// Forget the stored refresh token and cached access token.
+    pub fn clear_tokens(&mut self) {
+        self.refresh_token = None;
+        self.refresh_token_expires_at = None;
+        self.access_token = None;
+    }
+
+    /// Whether the stored refresh token is known to have expired.
+    pub fn refresh_token_expired(&self) -> bool {
+        self.refresh_token_expires_at
+            .is_some_and(|expires_at| expires_at <= unix_now())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct CachedToken {
+    pub access_token: String,
+    /// Unix time at which the access token expires.
+    pub expires_at: u64,
+    /// ID token issued alongside the access token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+    /// Scopes granted, as reported by the token endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+impl CachedToken {
+    /// Whether the token is still valid for at least `skew`.
+    pub fn is_valid(&self, skew: Duration) -> bool {
+        unix_now() + skew.as_secs() < self.expires_at
+    }
+}
+
+/// Endpoints published at `{issuer}/.well-known/openid-configuration`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub token_endpoint: String,
+    pub authorization_endpoint: Option<String>,
+    pub device_authorization_endpoint: Option<String>,
+    pub revocation_endpoint: Option<String>,
+    pub introspection_endpoint: Option<String>,
+    pub userinfo_endpoint: Option<String>,
+    pub jwks_uri: Option<String>,
+    pub end_session_endpoint: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+impl ProviderMetadata {
+    /// Keycloak's endpoint layout under a realm URL, used when discovery is unavailable.
+    pub fn keycloak(realm_url: &str) -> Self {
+        let base = format!("{realm_url}/protocol/openid-connect");
+        Self {
+            issuer: realm_url.to_string(),
+            token_endpoint: format!("{base}/token"),
+            authorization_endpoint: Some(format!("{base}/auth")),
+            device_authorization_endpoint: Some(format!("{base}/auth/device")),
+            revocation_endpoint: Some(format!("{base}/revoke")),
+            introspection_endpoint: Some(format!("{base}/token/introspect")),
+            userinfo_endpoint: Some(format!("{base}/userinfo")),
+            jwks_uri: Some(format!("{base}/certs")),
+            end_session_endpoint: Some(format!("{base}/logout")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
 pub struct ConfigFile {
     pub clients: HashMap<String, AuthConfig>,
+    /// Seconds before expiry at which a cached access token is no longer reused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_skew: Option<u64>,
+    /// Present when refresh tokens and secrets are encrypted at rest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault: Option<VaultHeader>,
+    /// Clients that `git-credential` answers for, by `host` or `protocol://host`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub git_hosts: HashMap<String, HostMapping>,
+    /// Clients that the docker credential helper answers for, by registry hostname.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub registries: HashMap<String, HostMapping>,
+    /// Output templates for `get --template-name`, by name.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub templates: HashMap<String, String>,
+}
+
+/// Username credential helpers send when a mapping doesn't name one. Servers that
+/// take a token as the password generally ignore it, but the protocols require one.
+pub const DEFAULT_HOST_USERNAME: &str = "oauth2";
+
+/// A stored client that credential helpers use for a host.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct HostMapping {
+    pub nickname: String,
+    /// Username to send alongside the access token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct TokenResponse {
     pub access_token: String,
     pub refresh_token: Option<String>,
+    pub expires_in: Option<u64>,
+    pub refresh_expires_in: Option<u64>,
+    pub id_token: Option<String>,
+    pub scope: Option<String>,
 }
 
-pub trait CredentialsProvider {
+/// Seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize)]
+pub struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: u64,
+    pub interval: Option<u64>,
+}
+
+pub trait CredentialsProvider: Send + Sync {
     fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>>;
 }
 
+/// Prompts for a username and password on the terminal. Fails with
+/// [`TokensError::LoginRequired`](crate::error::TokensError::LoginRequired) when
+/// stdin is not a terminal.
 pub struct ConsoleCredentialsProvider;
 
 impl CredentialsProvider for ConsoleCredentialsProvider {
     fn get_credentials(&self) -> Result<(String, String), Box<dyn std::error::Error>> {
         use rpassword::read_password;
-        use std::io::{self, Write};
+        use std::io::{self, IsTerminal, Write};
+
+        if !io::stdin().is_terminal() {
+            return Err(crate::error::TokensError::LoginRequired.into());
+        }
 
         print!("Username: ");
         io::stdout().flush()?;
         let mut username = String::new();
-        io::stdin().read_line(&mut username)?;
+        if io::stdin().read_line(&mut username)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Login aborted.").into());
+        }
         let username = username.trim().to_string();
 
         print!("Password: ");
@@ -50,7 +206,11 @@ mod tests {
 
     use std::collections::HashMap;
 
-    use crate::types::{AuthConfig, ConfigFile, CredentialsProvider, TokenResponse};
+    use crate::types::{
+        AuthConfig, CachedToken, ConfigFile, CredentialsProvider, GrantType, ProviderMetadata,
+        TokenResponse, unix_now,
+    };
+    use std::time::Duration;
 
     #[test]
     fn test_auth_config_serialization() {
@@ -59,6 +219,7 @@ mod tests {
             client_id: "client123".to_string(),
             refresh_token: Some("refresh123".to_string()),
             secret: None,
+            ..Default::default()
         };
 
         let serialized = serde_json::to_string(&auth_config).unwrap();
@@ -74,6 +235,7 @@ mod tests {
             client_id: "client123".to_string(),
             refresh_token: None,
             secret: None,
+            ..Default::default()
         };
 
         let serialized = serde_json::to_string(&auth_config).unwrap();
@@ -82,6 +244,42 @@ mod tests {
         assert_eq!(auth_config, deserialized);
     }
 
+    #[test]
+    fn test_auth_config_grant_type_defaults_to_password() {
+        let json = r#"{"auth_url": "https://example.com", "client_id": "client123", "refresh_token": null, "secret": null}"#;
+        let auth_config: AuthConfig = serde_json::from_str(json).unwrap();
+
+        assert_eq!(auth_config.grant_type, GrantType::Password);
+    }
+
+    #[test]
+    fn test_provider_metadata_deserialization_ignores_unknown_fields() {
+        let json = r#"{
+            "issuer": "https://example.com",
+            "token_endpoint": "https://example.com/oauth/token",
+            "jwks_uri": "https://example.com/.well-known/jwks.json",
+            "response_types_supported": ["code"]
+        }"#;
+        let metadata: ProviderMetadata = serde_json::from_str(json).unwrap();
+
+        assert_eq!(metadata.token_endpoint, "https://example.com/oauth/token");
+        assert_eq!(metadata.device_authorization_endpoint, None);
+    }
+
+    #[test]
+    fn test_provider_metadata_keycloak_fallback() {
+        let metadata = ProviderMetadata::keycloak("https://example.com/realms/master");
+
+        assert_eq!(
+            metadata.token_endpoint,
+            "https://example.com/realms/master/protocol/openid-connect/token"
+        );
+        assert_eq!(
+            metadata.jwks_uri.as_deref(),
+            Some("https://example.com/realms/master/protocol/openid-connect/certs")
+        );
+    }
+
     #[test]
     fn test_config_file_default() {
         let config = ConfigFile::default();
@@ -98,10 +296,14 @@ mod tests {
                 client_id: "client123".to_string(),
                 refresh_token: Some("refresh123".to_string()),
                 secret: None,
+                ..Default::default()
             },
         );
 
-        let config_file = ConfigFile { clients };
+        let config_file = ConfigFile {
+            clients,
+            ..Default::default()
+        };
 
         let serialized = serde_json::to_string(&config_file).unwrap();
         let deserialized: ConfigFile = serde_json::from_str(&serialized).unwrap();
@@ -118,6 +320,28 @@ mod tests {
         assert_eq!(token_response.refresh_token, Some("refresh123".to_string()));
     }
 
+    #[test]
+    fn test_token_response_deserialization_with_expiry() {
+        let json =
+            r#"{"access_token": "access123", "expires_in": 300, "refresh_expires_in": 1800}"#;
+        let token_response: TokenResponse = serde_json::from_str(json).unwrap();
+
+        assert_eq!(token_response.expires_in, Some(300));
+        assert_eq!(token_response.refresh_expires_in, Some(1800));
+    }
+
+    #[test]
+    fn test_cached_token_validity_respects_skew() {
+        let token = CachedToken {
+            access_token: "access123".to_string(),
+            expires_at: unix_now() + 60,
+            ..Default::default()
+        };
+
+        assert!(token.is_valid(Duration::from_secs(30)));
+        assert!(!token.is_valid(Duration::from_secs(90)));
+    }
+
     #[test]
     fn test_token_response_deserialization_without_refresh_token() {
         let json = r#"{"access_token": "access123"}"#;
@@ -150,6 +374,22 @@ mod tests {
         assert_eq!(result.1, "testpass");
     }
 
+    #[test]
+    fn test_console_credentials_provider_requires_a_terminal() {
+        use std::io::IsTerminal;
+
+        if std::io::stdin().is_terminal() {
+            return;
+        }
+        let err = super::ConsoleCredentialsProvider
+            .get_credentials()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::error::TokensError>(),
+            Some(crate::error::TokensError::LoginRequired)
+        ));
+    }
+
     struct FailingCredentialsProvider;
 
     impl CredentialsProvider for FailingCredentialsProvider {
@@ -0,0 +1,261 @@
+use crate::types::ConfigFile;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Hex-encoded derived key, as printed by `tokens vault unlock`.
+pub const VAULT_KEY_ENV: &str = "TOKENS_VAULT_KEY";
+/// Passphrase to derive the key from, for non-interactive use.
+pub const VAULT_PASSPHRASE_ENV: &str = "TOKENS_VAULT_PASSPHRASE";
+
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const CHECK_PLAINTEXT: &str = "tokens-vault";
+const NONCE_LEN: usize = 24;
+
+/// Key derivation parameters and a check value, stored in the config when the vault is enabled.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct VaultHeader {
+    pub salt: String,
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    /// A known plaintext encrypted with the key, used to reject a wrong passphrase.
+    pub check: String,
+}
+
+/// Key derived from the vault passphrase with Argon2id.
+#[derive(Clone)]
+pub struct VaultKey([u8; 32]);
+
+impl VaultKey {
+    /// Create a new header with a fresh salt and return it with the derived key.
+    pub fn create(passphrase: &str) -> Result<(VaultHeader, Self), Box<dyn Error>> {
+        let params = Params::default();
+        let mut header = VaultHeader {
+            salt: STANDARD.encode(rand::random::<[u8; 16]>()),
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            check: String::new(),
+        };
+
+        let key = Self::derive_unchecked(passphrase, &header)?;
+        header.check = key.encrypt(CHECK_PLAINTEXT)?;
+        Ok((header, key))
+    }
+
+    /// Derive the key for an existing header, rejecting a wrong passphrase.
+    pub fn derive(passphrase: &str, header: &VaultHeader) -> Result<Self, Box<dyn Error>> {
+        Self::derive_unchecked(passphrase, header)?.checked(header)
+    }
+
+    /// Parse a key exported with [`VaultKey::to_hex`], rejecting one that doesn't match.
+    pub fn from_hex(hex: &str, header: &VaultHeader) -> Result<Self, Box<dyn Error>> {
+        let hex = hex.trim().as_bytes();
+        if hex.len() != 64 || !hex.iter().all(u8::is_ascii_hexdigit) {
+            return Err(format!("{VAULT_KEY_ENV} must be 64 hex characters.").into());
+        }
+
+        let mut key = [0u8; 32];
+        for (byte, pair) in key.iter_mut().zip(hex.chunks_exact(2)) {
+            // Both bytes are ASCII hex digits, so this can't fail.
+            let pair = std::str::from_utf8(pair)?;
+            *byte = u8::from_str_radix(pair, 16)?;
+        }
+
+        Self(key).checked(header)
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, Box<dyn Error>> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher()
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_bytes())
+            .map_err(|_| "Failed to encrypt value.")?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend(ciphertext);
+        Ok(format!("{ENCRYPTED_PREFIX}{}", STANDARD.encode(payload)))
+    }
+
+    pub fn decrypt(&self, value: &str) -> Result<String, Box<dyn Error>> {
+        let encoded = value
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .ok_or("Value is not encrypted.")?;
+        let payload = STANDARD.decode(encoded)?;
+        if payload.len() < NONCE_LEN {
+            return Err("Encrypted value is truncated.".into());
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Failed to decrypt value: wrong key or corrupted data.")?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    fn derive_unchecked(passphrase: &str, header: &VaultHeader) -> Result<Self, Box<dyn Error>> {
+        let params = Params::new(header.m_cost, header.t_cost, header.p_cost, Some(32))
+            .map_err(|e| format!("Invalid vault parameters: {e}"))?;
+        let salt = STANDARD.decode(&header.salt)?;
+
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| format!("Failed to derive vault key: {e}"))?;
+
+        Ok(Self(key))
+    }
+
+    fn checked(self, header: &VaultHeader) -> Result<Self, Box<dyn Error>> {
+        match self.decrypt(&header.check) {
+            Ok(check) if check == CHECK_PLAINTEXT => Ok(self),
+            _ => Err("Incorrect vault passphrase.".into()),
+        }
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new((&self.0).into())
+    }
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Encrypt every sensitive field that is still plaintext.
+pub fn encrypt_config(config: &mut ConfigFile, key: &VaultKey) -> Result<(), Box<dyn Error>> {
+    for value in sensitive_fields(config) {
+        if !is_encrypted(value) {
+            *value = key.encrypt(value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Decrypt every encrypted sensitive field. Plaintext fields from before the vault
+/// was enabled are left as they are and get encrypted on the next save.
+pub fn decrypt_config(config: &mut ConfigFile, key: &VaultKey) -> Result<(), Box<dyn Error>> {
+    for value in sensitive_fields(config) {
+        if is_encrypted(value) {
+            *value = key.decrypt(value)?;
+        }
+    }
+    Ok(())
+}
+
+fn sensitive_fields(config: &mut ConfigFile) -> impl Iterator<Item = &mut String> {
+    config.clients.values_mut().flat_map(|client| {
+        let (access_token, id_token) = match client.access_token.as_mut() {
+            Some(cached) => (Some(&mut cached.access_token), cached.id_token.as_mut()),
+            None => (None, None),
+        };
+        [
+            client.refresh_token.as_mut(),
+            client.secret.as_mut(),
+            access_token,
+            id_token,
+        ]
+        .into_iter()
+        .flatten()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AuthConfig;
+
+    fn fast_header(passphrase: &str) -> (VaultHeader, VaultKey) {
+        let mut header = VaultHeader {
+            salt: STANDARD.encode([7u8; 16]),
+            m_cost: 8,
+            t_cost: 1,
+            p_cost: 1,
+            check: String::new(),
+        };
+        let key = VaultKey::derive_unchecked(passphrase, &header).unwrap();
+        header.check = key.encrypt(CHECK_PLAINTEXT).unwrap();
+        (header, key)
+    }
+
+    #[test]
+    fn encrypt_round_trip() {
+        let (_, key) = fast_header("hunter2");
+
+        let encrypted = key.encrypt("refresh123").unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("refresh123"));
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "refresh123");
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let (header, _) = fast_header("hunter2");
+
+        assert!(VaultKey::derive("hunter2", &header).is_ok());
+        assert!(VaultKey::derive("wrong", &header).is_err());
+    }
+
+    #[test]
+    fn hex_key_round_trip() {
+        let (header, key) = fast_header("hunter2");
+
+        let restored = VaultKey::from_hex(&key.to_hex(), &header).unwrap();
+
+        assert_eq!(restored.0, key.0);
+        assert!(VaultKey::from_hex(&"00".repeat(32), &header).is_err());
+    }
+
+    #[test]
+    fn hex_key_rejects_non_ascii() {
+        let (header, _) = fast_header("hunter2");
+
+        // 64 bytes long, but `é` is two bytes and not a hex digit.
+        let key = format!("é{}", "0".repeat(62));
+        assert_eq!(key.len(), 64);
+
+        let Err(err) = VaultKey::from_hex(&key, &header) else {
+            panic!("a non-ASCII key was accepted");
+        };
+        assert!(err.to_string().contains("64 hex characters"));
+    }
+
+    #[test]
+    fn config_fields_migrate_from_plaintext() {
+        let (_, key) = fast_header("hunter2");
+        let mut config = ConfigFile::default();
+        config.clients.insert(
+            "client".to_string(),
+            AuthConfig {
+                auth_url: "https://example.com".to_string(),
+                client_id: "client123".to_string(),
+                refresh_token: Some("refresh123".to_string()),
+                secret: Some("secret123".to_string()),
+                ..Default::default()
+            },
+        );
+
+        encrypt_config(&mut config, &key).unwrap();
+        let client = &config.clients["client"];
+        assert!(is_encrypted(client.refresh_token.as_ref().unwrap()));
+        assert!(is_encrypted(client.secret.as_ref().unwrap()));
+        assert_eq!(client.client_id, "client123");
+
+        decrypt_config(&mut config, &key).unwrap();
+        let client = &config.clients["client"];
+        assert_eq!(client.refresh_token.as_deref(), Some("refresh123"));
+        assert_eq!(client.secret.as_deref(), Some("secret123"));
+    }
+}